@@ -0,0 +1,90 @@
+//
+// benches/broadphase.rs
+//
+// `cargo bench --bench broadphase -- 5000`: moves bodies through that many platforms,
+// once against every platform and once against the grid, and prints how long each took
+use bevy::prelude::*;
+use gravity_wizard::broadphase::SpatialGrid;
+use gravity_wizard::config::PhysicsConfig;
+use gravity_wizard::physics::*;
+use std::time::Instant;
+
+fn main() {
+    // cargo bench passes --bench along, the platform count is the first number
+    let platforms = std::env::args()
+        .skip(1)
+        .find_map(|arg| arg.parse().ok())
+        .unwrap_or(5000);
+    run_benchmark(platforms);
+}
+
+fn run_benchmark(platform_count: usize) {
+    const BODIES: usize = 1000;
+    const STEPS: usize = 10;
+    let dt = PHYSICS_TIMESTEP;
+
+    // same numbers on every run
+    let mut seed: u32 = 0x5eed;
+    let mut random = move || {
+        seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        (seed >> 8) as f32 / (1 << 24) as f32
+    };
+
+    // platforms scattered over a square, 150 pixels apart
+    let columns = (platform_count as f32).sqrt().ceil() as usize;
+    let platforms: Vec<Obstacle> = (0..platform_count)
+        .map(|i| {
+            let x = (i % columns) as f32 * 150.0 + random() * 50.0;
+            let y = (i / columns) as f32 * 150.0 + random() * 50.0;
+            let size = Vec2::new(40.0 + random() * 100.0, 10.0 + random() * 30.0);
+            Obstacle::fixed(Aabb {
+                min: Vec2::new(x, y),
+                max: Vec2::new(x, y) + size,
+            })
+        })
+        .collect();
+    let side = columns as f32 * 150.0;
+    let max_velocity = PhysicsConfig::default().max_vertical_velocity;
+    let bodies: Vec<(Aabb, Vec2)> = (0..BODIES)
+        .map(|_| {
+            let center = Vec2::new(random(), random()) * side;
+            let velocity = (Vec2::new(random(), random()) - 0.5) * 2.0 * max_velocity;
+            (Aabb::from_center(center, Vec2::splat(20.0)), velocity)
+        })
+        .collect();
+
+    let started = Instant::now();
+    let mut brute_force = bodies.clone();
+    for _ in 0..STEPS {
+        for (aabb, velocity) in brute_force.iter_mut() {
+            (*aabb, *velocity, _) = move_and_slide(*aabb, *velocity, dt, &platforms);
+        }
+    }
+    let brute_force_time = started.elapsed();
+
+    let started = Instant::now();
+    let grid = SpatialGrid::build(platforms.iter().map(|platform| &platform.aabb));
+    let build_time = started.elapsed();
+    let mut found = Vec::new();
+    let mut nearby = Vec::new();
+    let mut with_grid = bodies;
+    for _ in 0..STEPS {
+        for (aabb, velocity) in with_grid.iter_mut() {
+            grid.query(&aabb.swept(*velocity * dt), &mut found);
+            nearby.clear();
+            nearby.extend(found.iter().map(|index| platforms[*index]));
+            (*aabb, *velocity, _) = move_and_slide(*aabb, *velocity, dt, &nearby);
+        }
+    }
+    let grid_time = started.elapsed() - build_time;
+
+    println!(
+        "{} platforms, {} bodies, {} steps",
+        platform_count, BODIES, STEPS
+    );
+    println!("brute force: {:?}", brute_force_time);
+    println!("grid:        {:?} (+ {:?} to build)", grid_time, build_time);
+    if brute_force != with_grid {
+        println!("the grid and brute force disagree!");
+    }
+}
@@ -0,0 +1,121 @@
+//
+// broadphase.rs
+//
+// a uniform grid over boxes, so collision only looks at what is nearby instead of
+// at every platform in the level
+use crate::layout::*;
+use crate::moving_platform::PlatformPath;
+use crate::physics::*;
+use bevy::prelude::*;
+use std::collections::HashMap;
+
+// a few times the wizard's size: most bodies span one to four cells
+pub const GRID_CELL_SIZE: f32 = 64.0;
+
+// boxes by the cells they touch. only indices are stored, the boxes live with the caller
+#[derive(Default)]
+pub struct SpatialGrid {
+    cells: HashMap<IVec2, Vec<usize>>,
+}
+
+impl SpatialGrid {
+    fn cells(aabb: &Aabb) -> impl Iterator<Item = IVec2> {
+        let min = (aabb.min / GRID_CELL_SIZE).floor().as_ivec2();
+        let max = (aabb.max / GRID_CELL_SIZE).floor().as_ivec2();
+        (min.y..=max.y).flat_map(move |y| (min.x..=max.x).map(move |x| IVec2::new(x, y)))
+    }
+
+    pub fn build<'a>(aabbs: impl Iterator<Item = &'a Aabb>) -> Self {
+        let mut grid = SpatialGrid::default();
+        for (index, aabb) in aabbs.enumerate() {
+            grid.insert(index, aabb);
+        }
+        grid
+    }
+
+    // the same index can be inserted again with another box, e.g. where a body ended up
+    pub fn insert(&mut self, index: usize, aabb: &Aabb) {
+        for cell in SpatialGrid::cells(aabb) {
+            self.cells.entry(cell).or_default().push(index);
+        }
+    }
+
+    // everything that might touch aabb, each index once and in ascending order, so the
+    // result is the same as looping over all of them
+    pub fn query(&self, aabb: &Aabb, found: &mut Vec<usize>) {
+        found.clear();
+        for cell in SpatialGrid::cells(aabb) {
+            if let Some(indices) = self.cells.get(&cell) {
+                found.extend(indices);
+            }
+        }
+        found.sort_unstable();
+        found.dedup();
+    }
+}
+
+// the level's platforms as obstacles. the ones that stand still have a grid over them,
+// rebuilt by update_platform_grid whenever one is added, changed or removed. moving
+// platforms are few and change every step, they are kept aside and checked directly
+#[derive(Resource, Default)]
+pub struct PlatformGrid {
+    pub platforms: Vec<Obstacle>,
+    pub grid: SpatialGrid,
+    pub moving: Vec<Obstacle>,
+    // what each moving platform covers during the next step
+    pub moving_swept: Vec<Aabb>,
+}
+
+impl PlatformGrid {
+    pub fn query<'a>(
+        &'a self,
+        aabb: &Aabb,
+        found: &'a mut Vec<usize>,
+    ) -> impl Iterator<Item = &'a Obstacle> + 'a {
+        self.grid.query(aabb, found);
+        let aabb = *aabb;
+        found.iter().map(|index| &self.platforms[*index]).chain(
+            self.moving
+                .iter()
+                .zip(self.moving_swept.iter())
+                .filter(move |(_, swept)| swept.expanded(CONTACT_DISTANCE).overlaps(&aabb))
+                .map(|(platform, _)| platform),
+        )
+    }
+}
+
+pub fn update_platform_grid(
+    mut platform_grid: ResMut<PlatformGrid>,
+    static_query: Query<&Platform, Without<PlatformPath>>,
+    changed_query: Query<(), (Changed<Platform>, Without<PlatformPath>)>,
+    moving_query: Query<(&Platform, &PlatformPath)>,
+    fixed_time: Res<FixedTime>,
+) {
+    let dt = physics_dt(&fixed_time);
+    let platform_grid = &mut *platform_grid;
+    platform_grid.moving.clear();
+    platform_grid.moving_swept.clear();
+    for (platform, path) in moving_query.iter() {
+        let obstacle = Obstacle {
+            velocity: path.velocity,
+            surface_velocity: path.velocity,
+            ..Obstacle::from(platform)
+        };
+        platform_grid.moving.push(obstacle);
+        platform_grid
+            .moving_swept
+            .push(obstacle.aabb.swept(obstacle.velocity * dt));
+    }
+
+    // removing a platform changes the count, adding or changing one shows up as changed
+    if changed_query.is_empty() && static_query.iter().len() == platform_grid.platforms.len() {
+        return;
+    }
+    platform_grid.platforms = static_query.iter().map(Obstacle::from).collect();
+    platform_grid.grid = SpatialGrid::build(
+        platform_grid
+            .platforms
+            .iter()
+            .map(|platform| &platform.aabb),
+    );
+}
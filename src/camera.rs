@@ -0,0 +1,83 @@
+//
+// camera.rs
+//
+// the camera follows the wizard through levels bigger than the window, without
+// showing anything outside the level's bounds
+use crate::layout::WorldBounds;
+use crate::physics::BodyGravity;
+use crate::wizard::Wizard;
+use bevy::{prelude::*, window::PrimaryWindow};
+
+// how far the wizard can move away from the camera's focus before it follows
+pub const CAMERA_DEAD_ZONE: Vec2 = Vec2::new(80.0, 60.0);
+// how far the camera looks ahead in the direction the wizard falls
+pub const CAMERA_LOOK_AHEAD: f32 = 120.0;
+// how quickly the camera catches up with its focus, higher is snappier
+pub const CAMERA_SMOOTHING: f32 = 5.0;
+
+#[derive(Component, Default)]
+pub struct FollowCamera {
+    // where the camera is heading
+    pub focus: Vec2,
+}
+
+// moves a view center so the view stays inside the world. a world smaller than the
+// window gets centered instead
+fn clamp_to_bounds(center: Vec2, bounds: &WorldBounds, half_view: Vec2) -> Vec2 {
+    let clamp_axis = |value: f32, low: f32, high: f32, half: f32| {
+        if high - low <= half * 2.0 {
+            (low + high) / 2.0
+        } else {
+            value.clamp(low + half, high - half)
+        }
+    };
+    Vec2::new(
+        clamp_axis(center.x, bounds.lowx, bounds.highx, half_view.x),
+        clamp_axis(center.y, bounds.lowy, bounds.highy, half_view.y),
+    )
+}
+
+// runs after the wizard has been interpolated, so it follows what is drawn
+pub fn camera_follow(
+    time: Res<Time>,
+    wizard_query: Query<(&Transform, &BodyGravity), With<Wizard>>,
+    spawned_query: Query<(), Added<Wizard>>,
+    mut camera_query: Query<(&mut Transform, &mut FollowCamera), Without<Wizard>>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    bounds: Res<WorldBounds>,
+) {
+    let Ok((wizard_transform, gravity)) = wizard_query.get_single() else {
+        return;
+    };
+    let Ok((mut camera_transform, mut camera)) = camera_query.get_single_mut() else {
+        return;
+    };
+    // the window is gone for the rest of the frame once it has been closed
+    let Ok(window) = window_query.get_single() else {
+        return;
+    };
+    let half_view = Vec2::new(window.width(), window.height()) / 2.0;
+    let target =
+        wizard_transform.translation.truncate() + gravity.direction.down() * CAMERA_LOOK_AHEAD;
+
+    // a fresh wizard, on load or after dying: jump there instead of sweeping across the level
+    let snap = !spawned_query.is_empty();
+    if snap {
+        camera.focus = target;
+    } else {
+        let offset = target - camera.focus;
+        camera.focus += offset - offset.clamp(-CAMERA_DEAD_ZONE, CAMERA_DEAD_ZONE);
+    }
+    camera.focus = clamp_to_bounds(camera.focus, &bounds, half_view);
+
+    let position = if snap {
+        camera.focus
+    } else {
+        // framerate independent easing towards the focus
+        let position = camera_transform.translation.truncate();
+        let blend = 1.0 - (-CAMERA_SMOOTHING * time.delta_seconds()).exp();
+        position + (camera.focus - position) * blend
+    };
+    camera_transform.translation.x = position.x;
+    camera_transform.translation.y = position.y;
+}
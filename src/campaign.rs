@@ -0,0 +1,141 @@
+//
+// campaign.rs
+//
+// the sequence of levels played one after the other, and the player's progress through it
+use crate::layout::DeathCounterText;
+use crate::level::find_level_files;
+use crate::menu::spawn_text_screen;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+
+pub const LEVELS_DIR: &str = "levels";
+pub const PROGRESS_FILE: &str = "progress.ron";
+
+#[derive(Resource)]
+pub struct Campaign {
+    pub levels: Vec<String>,
+    pub current: usize,
+}
+
+impl Campaign {
+    pub fn from_dir(dir: &str) -> Self {
+        Campaign {
+            levels: find_level_files(dir),
+            current: 0,
+        }
+    }
+
+    // None once every level has been beaten
+    pub fn current_level(&self) -> Option<&str> {
+        self.levels.get(self.current).map(|path| path.as_str())
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.current >= self.levels.len()
+    }
+}
+
+// saved between runs. levels are unlocked in campaign order
+#[derive(Resource, Default, Serialize, Deserialize)]
+pub struct Progress {
+    // every level up to and including this index can be played
+    pub unlocked: usize,
+    // best completion time in seconds, by level path
+    pub best_times: HashMap<String, f32>,
+}
+
+impl Progress {
+    // a missing or broken progress file just means starting from scratch
+    pub fn load(path: &str) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|source| ron::from_str(&source).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &str) {
+        let result = ron::to_string(self)
+            .map_err(|e| e.to_string())
+            .and_then(|data| fs::write(path, data).map_err(|e| e.to_string()));
+        if let Err(error) = result {
+            eprintln!("{}: could not save progress: {}", path, error);
+        }
+    }
+
+    pub fn is_unlocked(&self, index: usize) -> bool {
+        index <= self.unlocked
+    }
+
+    pub fn best_time(&self, path: &str) -> Option<f32> {
+        self.best_times.get(path).copied()
+    }
+
+    pub fn complete(&mut self, index: usize, path: &str, time: f32) {
+        self.unlocked = self.unlocked.max(index + 1);
+        if self.best_time(path).map_or(true, |best| time < best) {
+            self.best_times.insert(path.to_string(), time);
+        }
+    }
+}
+
+// seconds spent in the current level, not counting the pause screen
+#[derive(Resource, Default)]
+pub struct LevelTimer(pub f32);
+
+// times the wizard died in the current level
+#[derive(Resource, Default)]
+pub struct Deaths(pub u32);
+
+#[derive(Component)]
+pub struct CampaignCompleteText;
+
+pub fn advance_campaign(mut campaign: ResMut<Campaign>) {
+    campaign.current += 1;
+}
+
+pub fn reset_level_timer(mut timer: ResMut<LevelTimer>) {
+    timer.0 = 0.0;
+}
+
+pub fn reset_deaths(mut deaths: ResMut<Deaths>) {
+    deaths.0 = 0;
+}
+
+pub fn count_death(mut deaths: ResMut<Deaths>) {
+    deaths.0 += 1;
+}
+
+pub fn update_death_counter(
+    deaths: Res<Deaths>,
+    mut text_query: Query<&mut Text, With<DeathCounterText>>,
+) {
+    for mut text in text_query.iter_mut() {
+        text.sections[0].value = format!("Deaths: {}", deaths.0);
+    }
+}
+
+pub fn tick_level_timer(mut timer: ResMut<LevelTimer>, time: Res<Time>) {
+    timer.0 += time.delta_seconds();
+}
+
+pub fn record_level_time(
+    campaign: Res<Campaign>,
+    timer: Res<LevelTimer>,
+    mut progress: ResMut<Progress>,
+) {
+    let Some(path) = campaign.current_level() else {
+        return;
+    };
+    progress.complete(campaign.current, path, timer.0);
+    progress.save(PROGRESS_FILE);
+}
+
+pub fn show_campaign_complete(mut commands: Commands) {
+    spawn_text_screen(
+        &mut commands,
+        CampaignCompleteText,
+        &["Campaign complete!", "Enter: main menu"],
+    );
+}
@@ -0,0 +1,147 @@
+//
+// config.rs
+//
+// physics tuning: read from a file at startup, tweaked per level and reloaded
+// whenever the file changes, so the feel can be tuned while the game runs
+use crate::level::PhysicsOverrides;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::ErrorKind;
+use std::time::SystemTime;
+
+pub const PHYSICS_CONFIG_FILE: &str = "physics.ron";
+// how often the config file is checked for changes, in seconds
+pub const CONFIG_RELOAD_INTERVAL: f32 = 0.5;
+
+// accelerations are in pixels per second squared, velocities in pixels per second.
+// horizontal and vertical are relative to gravity: along the floor and along the fall.
+// fields missing from the file keep their default
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PhysicsConfig {
+    pub gravity: f32,
+    // walking, scaled by the floor's material on the ground
+    pub ground_acceleration: f32,
+    pub air_acceleration: f32,
+    // slowing down when not walking
+    pub ground_friction: f32,
+    pub air_friction: f32,
+    pub max_horizontal_velocity: f32,
+    pub max_vertical_velocity: f32,
+    // how fast a crate slows down once nobody pushes it
+    pub object_friction: f32,
+    pub wizard_size: f32,
+}
+
+// tuned per frame at 60 fps, hence the * 60.0
+impl Default for PhysicsConfig {
+    fn default() -> Self {
+        PhysicsConfig {
+            gravity: 9.8 * 1.5 * 60.0,
+            ground_acceleration: 4.0 * 60.0,
+            air_acceleration: 4.0 * 60.0,
+            ground_friction: 4.0 * 60.0,
+            air_friction: 4.0 * 60.0,
+            max_horizontal_velocity: 250.0,
+            max_vertical_velocity: 980.0,
+            object_friction: 8.0 * 60.0,
+            wizard_size: 20.0,
+        }
+    }
+}
+
+impl PhysicsConfig {
+    // a missing file means the defaults, a broken or unreadable one is reported and ignored
+    pub fn load(path: &str) -> Result<Self, String> {
+        match fs::read_to_string(path) {
+            Ok(source) => ron::from_str(&source).map_err(|e| format!("{}: {}", path, e)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(PhysicsConfig::default()),
+            Err(e) => Err(format!("{}: {}", path, e)),
+        }
+    }
+
+    pub fn with_overrides(&self, overrides: &PhysicsOverrides) -> Self {
+        PhysicsConfig {
+            gravity: overrides.gravity.unwrap_or(self.gravity),
+            ground_acceleration: overrides
+                .ground_acceleration
+                .unwrap_or(self.ground_acceleration),
+            air_acceleration: overrides.air_acceleration.unwrap_or(self.air_acceleration),
+            ground_friction: overrides.ground_friction.unwrap_or(self.ground_friction),
+            air_friction: overrides.air_friction.unwrap_or(self.air_friction),
+            max_horizontal_velocity: overrides
+                .max_horizontal_velocity
+                .unwrap_or(self.max_horizontal_velocity),
+            max_vertical_velocity: overrides
+                .max_vertical_velocity
+                .unwrap_or(self.max_vertical_velocity),
+            object_friction: overrides.object_friction.unwrap_or(self.object_friction),
+            wizard_size: overrides.wizard_size.unwrap_or(self.wizard_size),
+        }
+    }
+}
+
+// the config as read from the file, before the level's overrides
+#[derive(Resource)]
+pub struct PhysicsConfigFile {
+    pub path: String,
+    pub config: PhysicsConfig,
+    modified: Option<SystemTime>,
+    timer: Timer,
+}
+
+impl PhysicsConfigFile {
+    pub fn load(path: &str) -> Self {
+        let config = PhysicsConfig::load(path).unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            PhysicsConfig::default()
+        });
+        PhysicsConfigFile {
+            path: path.to_string(),
+            config,
+            modified: modified_time(path),
+            timer: Timer::from_seconds(CONFIG_RELOAD_INTERVAL, TimerMode::Repeating),
+        }
+    }
+}
+
+fn modified_time(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+// set by the level loader, cleared with the level
+#[derive(Resource, Default)]
+pub struct LevelPhysics(pub PhysicsOverrides);
+
+// reloads the file when it changed on disk and applies the level's overrides on top.
+// a broken file keeps the last good config
+pub fn update_physics_config(
+    time: Res<Time>,
+    mut config_file: ResMut<PhysicsConfigFile>,
+    level_physics: Res<LevelPhysics>,
+    mut config: ResMut<PhysicsConfig>,
+) {
+    let mut reloaded = false;
+    if config_file.timer.tick(time.delta()).just_finished() {
+        let modified = modified_time(&config_file.path);
+        if modified != config_file.modified {
+            config_file.modified = modified;
+            match PhysicsConfig::load(&config_file.path) {
+                Ok(loaded) => {
+                    println!("Reloaded {}", config_file.path);
+                    config_file.config = loaded;
+                    reloaded = true;
+                }
+                Err(error) => eprintln!("{}", error),
+            }
+        }
+    }
+    if reloaded || level_physics.is_changed() {
+        let updated = config_file.config.with_overrides(&level_physics.0);
+        // only touch the resource when something changed, so is_changed means something
+        if *config != updated {
+            *config = updated;
+        }
+    }
+}
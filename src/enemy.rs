@@ -0,0 +1,133 @@
+//
+// enemy.rs
+//
+// enemies walk along the floor like the wizard and fall with gravity like everything
+// else, so flipping gravity is also a way to get rid of them
+use crate::broadphase::PlatformGrid;
+use crate::layout::*;
+use crate::level::EnemyBehavior;
+use crate::physics::*;
+use crate::wizard::Wizard;
+use bevy::prelude::*;
+
+pub const ENEMY_COLOR: Color = Color::rgb(0.6, 0.1, 0.6);
+// how close the wizard has to get before a chasing enemy notices it
+pub const CHASE_RANGE: f32 = 250.0;
+// how far past its front edge an enemy looks for the end of its platform
+const LEDGE_PROBE: f32 = 2.0;
+
+#[derive(Component)]
+pub struct Enemy {
+    pub behavior: EnemyBehavior,
+    pub speed: f32,
+    // walking direction along the floor, 1.0 or -1.0
+    pub heading: f32,
+}
+
+// patrol: walk until a wall or the end of the floor, then turn around.
+// chase: walk towards the wizard when it is in range, even off a ledge.
+// only on the ground, in the air enemies keep whatever speed they had
+#[allow(clippy::type_complexity)]
+pub fn enemy_ai(
+    mut enemy_query: Query<
+        (
+            &mut Enemy,
+            &mut Velocity,
+            &Transform,
+            &EntitySize,
+            &BodyGravity,
+            &Contacts,
+        ),
+        Without<Wizard>,
+    >,
+    wizard_query: Query<&Transform, With<Wizard>>,
+    object_query: Query<(&Transform, &EntitySize), (With<Object>, Without<Enemy>)>,
+    platform_grid: Res<PlatformGrid>,
+) {
+    let wizard = wizard_query
+        .get_single()
+        .ok()
+        .map(|transform| transform.translation.truncate());
+    let mut found = Vec::new();
+    for (mut enemy, mut velocity, transform, size, gravity, contacts) in enemy_query.iter_mut() {
+        if !contacts.floor {
+            continue;
+        }
+        let center = transform.translation.truncate();
+        let axis = gravity.direction.right();
+        let down = gravity.direction.down();
+
+        let target = wizard.filter(|wizard| {
+            enemy.behavior == EnemyBehavior::Chase && wizard.distance(center) < CHASE_RANGE
+        });
+        if let Some(target) = target {
+            let towards = (target - center).dot(axis);
+            if towards != 0.0 {
+                enemy.heading = towards.signum();
+            }
+        } else {
+            let blocked = if enemy.heading > 0.0 {
+                contacts.right
+            } else {
+                contacts.left
+            };
+            // a small box just past the front edge, below the feet
+            let half = Vec2::new(size.width, size.height) / 2.0;
+            let forward = axis * enemy.heading;
+            let probe = Aabb::from_center(
+                center
+                    + forward * (half.dot(forward.abs()) + LEDGE_PROBE / 2.0)
+                    + down * (half.dot(down.abs()) + LEDGE_PROBE / 2.0),
+                Vec2::splat(LEDGE_PROBE),
+            );
+            let on_platform = platform_grid
+                .query(&probe, &mut found)
+                .any(|platform| platform.aabb.overlaps(&probe));
+            let on_object = object_query.iter().any(|(transform, size)| {
+                Aabb::from_center(
+                    transform.translation.truncate(),
+                    Vec2::new(size.width, size.height),
+                )
+                .overlaps(&probe)
+            });
+            if blocked || !(on_platform || on_object) {
+                enemy.heading = -enemy.heading;
+            }
+        }
+        *velocity_along(&mut velocity, axis) = enemy.heading * enemy.speed;
+    }
+}
+
+// enemies die when they leave the world or get squeezed between a crate that moved into
+// them and whatever is on their other side, a crate just lying on one is fine
+#[allow(clippy::type_complexity)]
+pub fn kill_enemies(
+    mut commands: Commands,
+    enemy_query: Query<
+        (
+            Entity,
+            &PhysicsInterpolation,
+            &EntitySize,
+            &BodyGravity,
+            &Contacts,
+        ),
+        With<Enemy>,
+    >,
+    object_query: Query<(&PhysicsInterpolation, &EntitySize), (With<Object>, Without<Enemy>)>,
+    bounds: Res<WorldBounds>,
+) {
+    for (entity, interpolation, size, gravity, contacts) in enemy_query.iter() {
+        let enemy = interpolation.aabb(size);
+        let crushed = object_query.iter().any(|(interpolation, size)| {
+            let object = interpolation.aabb(size);
+            // how far the crate moved during the last physics step
+            let moved = interpolation.current - interpolation.previous;
+            touching_side(&enemy, &object).is_some_and(|side| {
+                moved.dot(-side) > 0.0 && contacts.touches(-side, gravity.direction)
+            })
+        });
+        if bounds.is_outside(&enemy) || crushed {
+            commands.entity(entity).despawn();
+        }
+    }
+}
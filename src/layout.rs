@@ -2,10 +2,14 @@
 //
 // platform.rs
 //
+use crate::config::LevelPhysics;
+use crate::enemy::*;
+use crate::level::*;
+use crate::moving_platform::PlatformPath;
 use crate::physics::*;
-use crate::wizard::Wizard;
+use crate::wizard::{Inventory, Wizard};
 use bevy::prelude::*;
-use std::fs;
+use std::collections::HashMap;
 
 #[derive(Component)]
 pub struct Platform {
@@ -13,6 +17,16 @@ pub struct Platform {
     pub highx: f32,
     pub lowy: f32,
     pub highy: f32,
+    pub material: PlatformMaterial,
+}
+
+pub fn platform_color(material: PlatformMaterial) -> Color {
+    match material {
+        PlatformMaterial::Normal => Color::rgb(0.0, 0.0, 0.0),
+        PlatformMaterial::Ice => Color::rgb(0.6, 0.85, 1.0),
+        PlatformMaterial::Sticky => Color::rgb(0.35, 0.5, 0.1),
+        PlatformMaterial::Bouncy => Color::rgb(1.0, 0.4, 0.7),
+    }
 }
 
 // exit: a square that the player must squeeze into to win
@@ -28,73 +42,414 @@ const EXIT_SIZE: f32 = 18.0;
 
 pub fn is_in_exit(
     exit_query: Query<&Exit>,
-    wizard_transform_query: Query<&Transform, With<Wizard>>,
+    wizard_query: Query<&PhysicsInterpolation, With<Wizard>>,
 ) -> bool {
-    let translation = wizard_transform_query.single().translation;
-    let exit = exit_query.single();
-    (exit.x - translation.x).abs() < EXIT_MARGIN && (exit.y - translation.y).abs() < EXIT_MARGIN
+    let (Ok(interpolation), Ok(exit)) = (wizard_query.get_single(), exit_query.get_single()) else {
+        return false;
+    };
+    let position = interpolation.current;
+    (exit.x - position.x).abs() < EXIT_MARGIN && (exit.y - position.y).abs() < EXIT_MARGIN
+}
+
+// there is no level without an exit, so this is false before loading and after clearing
+pub fn level_loaded(exit_query: Query<(), With<Exit>>) -> bool {
+    !exit_query.is_empty()
 }
 
+// everything a level spawns, including the wizard, so unloading it leaves nothing behind
+#[derive(Component)]
+pub struct LevelEntity;
+
+// level title shown in the top left corner
+#[derive(Component)]
+pub struct LevelTitle;
+
+// a movable crate: falls with gravity, can be pushed by the wizard, and is not a platform
 #[derive(Component)]
 pub struct Object;
 
+pub const OBJECT_COLOR: Color = Color::rgb(0.55, 0.35, 0.15);
+
 #[derive(Component)]
-pub struct DeathZone;
+pub struct DeathZone {
+    pub lowx: f32,
+    pub highx: f32,
+    pub lowy: f32,
+    pub highy: f32,
+}
 
-// level creation/deletion
-pub fn level_data_reader(path: String, mut commands: Commands) {
-    let data = fs::read_to_string(path).expect("Failed to read level data");
-    let level_data: Vec<&str> = data.split(";").collect();
-    println!("{:#?}", level_data);
-
-    // let header = level_data[0].split("\n").collect::<Vec<&str>>();
-    // let title = header[0];
-    // let n_platforms = header[1].split(" ").collect::<Vec<&str>>()[1]
-    //     .parse::<usize>()
-    //     .unwrap();
-    // let n_objects = header[2].split(" ").collect::<Vec<&str>>()[1]
-    //     .parse::<usize>()
-    //     .unwrap();
-
-    let platforms = level_data[1].split(",").collect::<Vec<&str>>();
-    let mut platforms_vec = Vec::new();
-    for line in platforms.iter() {
-        let line = line.trim().split(" ").collect::<Vec<&str>>();
-        if line == vec!["NULL"] {
-            break;
-        }
-        platforms_vec.push(Platform {
-            lowx: line[0].parse::<f32>().unwrap(),
-            highx: line[1].parse::<f32>().unwrap(),
-            lowy: line[2].parse::<f32>().unwrap(),
-            highy: line[3].parse::<f32>().unwrap(),
-        });
+impl DeathZone {
+    // touching the edge is still safe
+    pub fn overlaps(&self, aabb: &Aabb) -> bool {
+        aabb.min.x < self.highx
+            && aabb.max.x > self.lowx
+            && aabb.min.y < self.highy
+            && aabb.max.y > self.lowy
+    }
+}
+
+pub const DEATH_ZONE_COLOR: Color = Color::rgb(1.0, 0.3, 0.0);
+
+// overrides the level's gravity for bodies that are fully inside
+#[derive(Component)]
+pub struct GravityZone {
+    pub lowx: f32,
+    pub highx: f32,
+    pub lowy: f32,
+    pub highy: f32,
+    pub direction: GravityDirection,
+    pub strength: f32,
+    // zones waiting for a signal are switched off until it comes
+    pub active: bool,
+}
+
+impl GravityZone {
+    pub fn contains(&self, lowx: f32, highx: f32, lowy: f32, highy: f32) -> bool {
+        lowx >= self.lowx && highx <= self.highx && lowy >= self.lowy && highy <= self.highy
+    }
+
+    pub fn area(&self) -> f32 {
+        (self.highx - self.lowx) * (self.highy - self.lowy)
     }
+}
+
+pub const GRAVITY_ZONE_COLOR: Color = Color::rgba(0.3, 0.5, 1.0, 0.25);
+pub const ZERO_GRAVITY_ZONE_COLOR: Color = Color::rgba(0.8, 0.8, 0.8, 0.25);
+
+// a key in the level. a held one is hidden and travels with the wizard's inventory
+#[derive(Component)]
+pub struct Key {
+    pub color: KeyColor,
+    pub held: bool,
+}
 
-    let objects = level_data[2].split(",").collect::<Vec<&str>>();
-    let mut objects_vec = Vec::new();
-    for line in objects.iter() {
-        let line = line.trim().split(" ").collect::<Vec<&str>>();
-        if line == vec!["NULL"] {
-            break;
+const KEY_SIZE: f32 = 10.0;
+
+// a closed door is also a Platform, opening it takes that away.
+// a door unlocked with a key stays open, whatever its signal does
+#[derive(Component)]
+pub struct Door {
+    pub lowx: f32,
+    pub highx: f32,
+    pub lowy: f32,
+    pub highy: f32,
+    pub color: Option<KeyColor>,
+    pub unlocked: bool,
+}
+
+impl From<&Door> for Platform {
+    fn from(door: &Door) -> Self {
+        Platform {
+            lowx: door.lowx,
+            highx: door.highx,
+            lowy: door.lowy,
+            highy: door.highy,
+            material: PlatformMaterial::Normal,
         }
-        objects_vec.push(Platform {
-            lowx: line[0].parse::<f32>().unwrap(),
-            highx: line[1].parse::<f32>().unwrap(),
-            lowy: line[2].parse::<f32>().unwrap(),
-            highy: line[3].parse::<f32>().unwrap(),
+    }
+}
+
+// doors that only open by signal
+pub const DOOR_COLOR: Color = Color::rgb(0.4, 0.4, 0.45);
+
+pub fn key_color(color: KeyColor) -> Color {
+    match color {
+        KeyColor::Red => Color::rgb(0.9, 0.1, 0.1),
+        KeyColor::Green => Color::rgb(0.1, 0.8, 0.2),
+        KeyColor::Blue => Color::rgb(0.2, 0.3, 1.0),
+        KeyColor::Yellow => Color::rgb(1.0, 0.85, 0.0),
+    }
+}
+
+// open doors stay visible as a faint outline of where they were
+const UNLOCKED_DOOR_ALPHA: f32 = 0.2;
+
+pub fn pick_up_keys(
+    mut wizard_query: Query<(&PhysicsInterpolation, &EntitySize, &mut Inventory), With<Wizard>>,
+    mut key_query: Query<(&mut Key, &Transform, &mut Visibility), Without<Wizard>>,
+) {
+    let Ok((interpolation, wizard_size, mut inventory)) = wizard_query.get_single_mut() else {
+        return;
+    };
+    let wizard = interpolation.aabb(wizard_size);
+    for (mut key, transform, mut visibility) in key_query.iter_mut() {
+        let bounds = Aabb::from_center(transform.translation.truncate(), Vec2::splat(KEY_SIZE));
+        if !key.held && wizard.overlaps(&bounds) {
+            key.held = true;
+            *visibility = Visibility::Hidden;
+            inventory.keys.push(key.color);
+        }
+    }
+}
+
+// touching a locked door with its key opens it for good and uses up the key
+pub fn unlock_doors(
+    mut commands: Commands,
+    mut wizard_query: Query<(&PhysicsInterpolation, &EntitySize, &mut Inventory), With<Wizard>>,
+    mut door_query: Query<(Entity, &mut Door, &Platform, &mut Sprite)>,
+    key_query: Query<(Entity, &Key)>,
+) {
+    let Ok((interpolation, wizard_size, mut inventory)) = wizard_query.get_single_mut() else {
+        return;
+    };
+    let wizard = interpolation.aabb(wizard_size).expanded(CONTACT_DISTANCE);
+    for (entity, mut door, platform, mut sprite) in door_query.iter_mut() {
+        let Some(color) = door.color else {
+            continue;
+        };
+        if !wizard.overlaps(&Aabb::from(platform)) {
+            continue;
+        }
+        let Some(index) = inventory.keys.iter().position(|held| *held == color) else {
+            continue;
+        };
+        inventory.keys.remove(index);
+        if let Some((key, _)) = key_query
+            .iter()
+            .find(|(_, key)| key.held && key.color == color)
+        {
+            commands.entity(key).despawn();
+        }
+        door.unlocked = true;
+        commands.entity(entity).remove::<Platform>();
+        sprite.color.set_a(UNLOCKED_DOOR_ALPHA);
+    }
+}
+
+// the keys the wizard was carrying go back to where they were found, so dying
+// can't leave a door locked for good
+pub fn drop_keys(mut key_query: Query<(&mut Key, &mut Visibility)>) {
+    for (mut key, mut visibility) in key_query.iter_mut() {
+        if key.held {
+            key.held = false;
+            *visibility = Visibility::Inherited;
+        }
+    }
+}
+
+// switches send signals, targets listen to one. the wiring is by name, from the level file
+#[derive(Component)]
+pub struct Switch {
+    pub lowx: f32,
+    pub highx: f32,
+    pub lowy: f32,
+    pub highy: f32,
+    pub kind: SwitchKind,
+    pub signal: String,
+    pub on: bool,
+    // whether something was on it last frame, levers flip when that starts
+    pub pressed: bool,
+    // seconds a button stays on
+    pub remaining: f32,
+}
+
+pub const SWITCH_OFF_COLOR: Color = Color::rgb(0.5, 0.5, 0.5);
+pub const SWITCH_ON_COLOR: Color = Color::rgb(1.0, 0.9, 0.3);
+
+// a door, moving platform or gravity zone that is only open, moving or working while
+// its signal is on
+#[derive(Component)]
+pub struct SignalTarget {
+    pub signal: String,
+}
+
+// sent whenever a signal turns on or off
+pub struct SignalChanged {
+    pub signal: String,
+    pub active: bool,
+}
+
+// the signals that are on, by name. cleared with the level
+#[derive(Resource, Default)]
+pub struct Signals {
+    pub active: HashMap<String, bool>,
+}
+
+// presses and releases switches, and sends SignalChanged for every signal whose switches
+// went from all off to any on, or back. uses where physics put the bodies
+#[allow(clippy::type_complexity)]
+pub fn update_switches(
+    time: Res<Time>,
+    mut switch_query: Query<(&mut Switch, &mut Sprite)>,
+    presser_query: Query<
+        (&PhysicsInterpolation, &EntitySize, Option<&Wizard>),
+        Or<(With<Wizard>, With<Object>)>,
+    >,
+    mut signals: ResMut<Signals>,
+    mut changed: EventWriter<SignalChanged>,
+) {
+    for (mut switch, mut sprite) in switch_query.iter_mut() {
+        let bounds = Aabb {
+            min: Vec2::new(switch.lowx, switch.lowy),
+            max: Vec2::new(switch.highx, switch.highy),
+        }
+        .expanded(CONTACT_DISTANCE);
+        // only pressure plates are heavy enough to care about crates
+        let pressed = presser_query.iter().any(|(interpolation, size, wizard)| {
+            (wizard.is_some() || switch.kind == SwitchKind::PressurePlate)
+                && bounds.overlaps(&interpolation.aabb(size))
         });
+        match switch.kind {
+            SwitchKind::PressurePlate => switch.on = pressed,
+            SwitchKind::Lever => {
+                if pressed && !switch.pressed {
+                    switch.on = !switch.on;
+                }
+            }
+            SwitchKind::Button { duration } => {
+                if pressed {
+                    switch.on = true;
+                    switch.remaining = duration;
+                } else if switch.on {
+                    switch.remaining -= time.delta_seconds();
+                    switch.on = switch.remaining > 0.0;
+                }
+            }
+        }
+        switch.pressed = pressed;
+        let color = if switch.on {
+            SWITCH_ON_COLOR
+        } else {
+            SWITCH_OFF_COLOR
+        };
+        if sprite.color != color {
+            sprite.color = color;
+        }
+    }
+
+    let mut active: HashMap<&str, bool> = HashMap::new();
+    for (switch, _) in switch_query.iter() {
+        *active.entry(&switch.signal).or_default() |= switch.on;
+    }
+    for (signal, on) in active {
+        if signals.active.get(signal).copied().unwrap_or(false) != on {
+            signals.active.insert(signal.to_string(), on);
+            changed.send(SignalChanged {
+                signal: signal.to_string(),
+                active: on,
+            });
+        }
+    }
+}
+
+// opens and closes doors, starts and stops moving platforms and switches gravity zones
+// on and off when their signal changes. doors follow their signal every frame: one that
+// should close waits until no body is in the doorway, it would end up stuck inside
+#[allow(clippy::type_complexity)]
+pub fn apply_signals(
+    mut commands: Commands,
+    mut changed: EventReader<SignalChanged>,
+    signals: Res<Signals>,
+    mut door_query: Query<(Entity, &Door, &SignalTarget, &mut Sprite, Option<&Platform>)>,
+    body_query: Query<(&PhysicsInterpolation, &EntitySize)>,
+    mut path_query: Query<(&mut PlatformPath, &SignalTarget)>,
+    mut zone_query: Query<(&mut GravityZone, &SignalTarget, &mut Visibility)>,
+) {
+    for (entity, door, target, mut sprite, platform) in door_query.iter_mut() {
+        if door.unlocked {
+            continue;
+        }
+        let open = signals.active.get(&target.signal).copied().unwrap_or(false);
+        if open && platform.is_some() {
+            commands.entity(entity).remove::<Platform>();
+            sprite.color.set_a(UNLOCKED_DOOR_ALPHA);
+        } else if !open && platform.is_none() {
+            let closed = Platform::from(door);
+            let doorway = Aabb::from(&closed);
+            let blocked = body_query
+                .iter()
+                .any(|(interpolation, size)| doorway.overlaps(&interpolation.aabb(size)));
+            if !blocked {
+                commands.entity(entity).insert(closed);
+                sprite.color.set_a(1.0);
+            }
+        }
+    }
+    for event in changed.iter() {
+        for (mut path, target) in path_query.iter_mut() {
+            if target.signal == event.signal {
+                path.active = event.active;
+            }
+        }
+        for (mut zone, target, mut visibility) in zone_query.iter_mut() {
+            if target.signal == event.signal {
+                zone.active = event.active;
+                *visibility = if event.active {
+                    Visibility::Inherited
+                } else {
+                    Visibility::Hidden
+                };
+            }
+        }
+    }
+}
+
+// the part of the world the level takes up, set by the level loader
+#[derive(Resource)]
+pub struct WorldBounds {
+    pub lowx: f32,
+    pub highx: f32,
+    pub lowy: f32,
+    pub highy: f32,
+}
+
+impl Default for WorldBounds {
+    fn default() -> Self {
+        WorldBounds::from(RectData {
+            lowx: 0.0,
+            highx: SCREEN_WIDTH,
+            lowy: 0.0,
+            highy: SCREEN_HEIGHT,
+        })
+    }
+}
+
+impl From<RectData> for WorldBounds {
+    fn from(rect: RectData) -> Self {
+        WorldBounds {
+            lowx: rect.lowx,
+            highx: rect.highx,
+            lowy: rect.lowy,
+            highy: rect.highy,
+        }
+    }
+}
+
+impl WorldBounds {
+    // a box that is only partly outside is still in the world
+    pub fn is_outside(&self, aabb: &Aabb) -> bool {
+        aabb.min.x > self.highx
+            || aabb.max.x < self.lowx
+            || aabb.min.y > self.highy
+            || aabb.max.y < self.lowy
     }
-    let exit_vec = level_data[3].trim().split(" ").collect::<Vec<&str>>();
+}
+
+// death counter shown in the top right corner
+#[derive(Component)]
+pub struct DeathCounterText;
 
-    // spawning
-    for platform in platforms_vec {
+// level creation/deletion
+impl From<PlatformData> for Platform {
+    fn from(data: PlatformData) -> Self {
+        Platform {
+            lowx: data.lowx,
+            highx: data.highx,
+            lowy: data.lowy,
+            highy: data.highy,
+            material: data.material,
+        }
+    }
+}
+
+pub fn spawn_level(commands: &mut Commands, level: &LevelData) {
+    for platform in level.platforms.iter().copied().map(Platform::from) {
         let x = (platform.lowx + platform.highx) / 2.0;
         let y = (platform.lowy + platform.highy) / 2.0;
         commands.spawn((
             SpriteBundle {
                 sprite: Sprite {
-                    color: Color::rgb(0.0, 0.0, 0.0),
+                    color: platform_color(platform.material),
                     custom_size: Some(Vec2::new(
                         platform.highx - platform.lowx,
                         platform.highy - platform.lowy,
@@ -105,30 +460,257 @@ pub fn level_data_reader(path: String, mut commands: Commands) {
                 ..default()
             },
             platform,
+            LevelEntity,
         ));
     }
-    for object in objects_vec {
+    for platform in level.moving_platforms.iter() {
+        let x = (platform.lowx + platform.highx) / 2.0;
+        let y = (platform.lowy + platform.highy) / 2.0;
+        let points = std::iter::once(Vec2::new(x, y))
+            .chain(
+                platform
+                    .waypoints
+                    .iter()
+                    .map(|point| Vec2::new(point.x, point.y)),
+            )
+            .collect();
+        let mut entity = commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: platform_color(platform.material),
+                    custom_size: Some(Vec2::new(
+                        platform.highx - platform.lowx,
+                        platform.highy - platform.lowy,
+                    )),
+                    ..default()
+                },
+                transform: Transform::from_xyz(x, y, 0.0),
+                ..default()
+            },
+            Platform {
+                lowx: platform.lowx,
+                highx: platform.highx,
+                lowy: platform.lowy,
+                highy: platform.highy,
+                material: platform.material,
+            },
+            PlatformPath {
+                active: platform.signal.is_none(),
+                ..PlatformPath::new(points, platform.mode, platform.speed)
+            },
+            PhysicsInterpolation::new(Vec2::new(x, y)),
+            LevelEntity,
+        ));
+        if let Some(signal) = &platform.signal {
+            entity.insert(SignalTarget {
+                signal: signal.clone(),
+            });
+        }
+    }
+    for object in level.objects.iter() {
         let x = (object.lowx + object.highx) / 2.0;
         let y = (object.lowy + object.highy) / 2.0;
+        let size = Vec2::new(object.highx - object.lowx, object.highy - object.lowy);
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: OBJECT_COLOR,
+                    custom_size: Some(size),
+                    ..default()
+                },
+                transform: Transform::from_xyz(x, y, 0.0),
+                ..default()
+            },
+            Object,
+            Velocity { x: 0.0, y: 0.0 },
+            PhysicsInterpolation::new(Vec2::new(x, y)),
+            EntitySize {
+                width: size.x,
+                height: size.y,
+            },
+            Contacts::default(),
+            BodyGravity::default(),
+            GravityResponse {
+                scale: object.gravity_scale,
+                fixed_direction: object.flip_immune.then_some(level.gravity),
+            },
+            Mass(object.mass),
+            LevelEntity,
+        ));
+    }
+    for zone in level.death_zones.iter() {
         commands.spawn((
             SpriteBundle {
                 sprite: Sprite {
-                    color: Color::rgb(0.0, 0.0, 0.0),
+                    color: DEATH_ZONE_COLOR,
+                    custom_size: Some(Vec2::new(zone.highx - zone.lowx, zone.highy - zone.lowy)),
+                    ..default()
+                },
+                transform: Transform::from_xyz(
+                    (zone.lowx + zone.highx) / 2.0,
+                    (zone.lowy + zone.highy) / 2.0,
+                    0.0,
+                ),
+                ..default()
+            },
+            DeathZone {
+                lowx: zone.lowx,
+                highx: zone.highx,
+                lowy: zone.lowy,
+                highy: zone.highy,
+            },
+            LevelEntity,
+        ));
+    }
+    for zone in level.gravity_zones.iter() {
+        let mut entity = commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: if zone.strength == 0.0 {
+                        ZERO_GRAVITY_ZONE_COLOR
+                    } else {
+                        GRAVITY_ZONE_COLOR
+                    },
+                    custom_size: Some(Vec2::new(zone.highx - zone.lowx, zone.highy - zone.lowy)),
+                    ..default()
+                },
+                // behind everything else
+                transform: Transform::from_xyz(
+                    (zone.lowx + zone.highx) / 2.0,
+                    (zone.lowy + zone.highy) / 2.0,
+                    -1.0,
+                ),
+                ..default()
+            },
+            GravityZone {
+                lowx: zone.lowx,
+                highx: zone.highx,
+                lowy: zone.lowy,
+                highy: zone.highy,
+                direction: zone.direction,
+                strength: zone.strength,
+                active: zone.signal.is_none(),
+            },
+            LevelEntity,
+        ));
+        if let Some(signal) = &zone.signal {
+            entity.insert((
+                SignalTarget {
+                    signal: signal.clone(),
+                },
+                Visibility::Hidden,
+            ));
+        }
+    }
+    for door in level.doors.iter() {
+        let x = (door.lowx + door.highx) / 2.0;
+        let y = (door.lowy + door.highy) / 2.0;
+        let closed = Door {
+            lowx: door.lowx,
+            highx: door.highx,
+            lowy: door.lowy,
+            highy: door.highy,
+            color: door.color,
+            unlocked: false,
+        };
+        let mut entity = commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: door.color.map_or(DOOR_COLOR, key_color),
+                    custom_size: Some(Vec2::new(door.highx - door.lowx, door.highy - door.lowy)),
+                    ..default()
+                },
+                transform: Transform::from_xyz(x, y, 0.0),
+                ..default()
+            },
+            Platform::from(&closed),
+            closed,
+            LevelEntity,
+        ));
+        if let Some(signal) = &door.signal {
+            entity.insert(SignalTarget {
+                signal: signal.clone(),
+            });
+        }
+    }
+    for switch in level.switches.iter() {
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: SWITCH_OFF_COLOR,
                     custom_size: Some(Vec2::new(
-                        object.highx - object.lowx,
-                        object.highy - object.lowy,
+                        switch.highx - switch.lowx,
+                        switch.highy - switch.lowy,
                     )),
                     ..default()
                 },
+                transform: Transform::from_xyz(
+                    (switch.lowx + switch.highx) / 2.0,
+                    (switch.lowy + switch.highy) / 2.0,
+                    0.0,
+                ),
+                ..default()
+            },
+            Switch {
+                lowx: switch.lowx,
+                highx: switch.highx,
+                lowy: switch.lowy,
+                highy: switch.highy,
+                kind: switch.kind,
+                signal: switch.signal.clone(),
+                on: false,
+                pressed: false,
+                remaining: 0.0,
+            },
+            LevelEntity,
+        ));
+    }
+    for enemy in level.enemies.iter() {
+        let x = (enemy.lowx + enemy.highx) / 2.0;
+        let y = (enemy.lowy + enemy.highy) / 2.0;
+        let size = Vec2::new(enemy.highx - enemy.lowx, enemy.highy - enemy.lowy);
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: ENEMY_COLOR,
+                    custom_size: Some(size),
+                    ..default()
+                },
                 transform: Transform::from_xyz(x, y, 0.0),
                 ..default()
             },
+            Enemy {
+                behavior: enemy.behavior,
+                speed: enemy.speed,
+                heading: 1.0,
+            },
             Velocity { x: 0.0, y: 0.0 },
+            PhysicsInterpolation::new(Vec2::new(x, y)),
             EntitySize {
-                width: object.highx - object.lowx,
-                height: object.highy - object.lowy,
+                width: size.x,
+                height: size.y,
             },
-            object,
+            Contacts::default(),
+            BodyGravity::default(),
+            LevelEntity,
+        ));
+    }
+    for key in level.keys.iter() {
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: key_color(key.color),
+                    custom_size: Some(Vec2::splat(KEY_SIZE)),
+                    ..default()
+                },
+                transform: Transform::from_xyz(key.x, key.y, 0.0),
+                ..default()
+            },
+            Key {
+                color: key.color,
+                held: false,
+            },
+            LevelEntity,
         ));
     }
     commands.spawn((
@@ -138,28 +720,149 @@ pub fn level_data_reader(path: String, mut commands: Commands) {
                 custom_size: Some(Vec2::new(EXIT_SIZE, EXIT_SIZE)),
                 ..default()
             },
-            transform: Transform::from_xyz(
-                exit_vec[0].parse::<f32>().unwrap(),
-                exit_vec[1].parse::<f32>().unwrap(),
-                0.0,
-            ),
+            transform: Transform::from_xyz(level.exit.x, level.exit.y, 0.0),
             ..default()
         },
         Exit {
-            x: exit_vec[0].parse::<f32>().unwrap(),
-            y: exit_vec[1].parse::<f32>().unwrap(),
+            x: level.exit.x,
+            y: level.exit.y,
         },
+        LevelEntity,
+    ));
+    commands.spawn((
+        TextBundle::from_section(
+            level.meta.title.clone(),
+            TextStyle {
+                font_size: 24.0,
+                color: Color::WHITE,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                left: Val::Px(10.0),
+                top: Val::Px(10.0),
+                ..default()
+            },
+            ..default()
+        }),
+        LevelTitle,
+        LevelEntity,
+    ));
+    commands.spawn((
+        TextBundle::from_section(
+            "Deaths: 0",
+            TextStyle {
+                font_size: 24.0,
+                color: Color::WHITE,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                right: Val::Px(10.0),
+                top: Val::Px(10.0),
+                ..default()
+            },
+            ..default()
+        }),
+        DeathCounterText,
+        LevelEntity,
     ));
 }
 
+// also puts gravity, physics, signals and the world bounds back to normal, the next level
+// decides what it uses
 pub fn clear_level(
     mut commands: Commands,
-    platform_query: Query<Entity, With<Platform>>,
-    exit_query: Query<Entity, With<Exit>>,
+    level_query: Query<Entity, With<LevelEntity>>,
+    mut gravity: ResMut<Gravity>,
+    mut level_physics: ResMut<LevelPhysics>,
+    mut signals: ResMut<Signals>,
+    mut bounds: ResMut<WorldBounds>,
 ) {
-    for platform in platform_query.iter() {
-        commands.entity(platform).despawn();
+    for entity in level_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    gravity.0 = GravityDirection::default();
+    *level_physics = LevelPhysics::default();
+    signals.active.clear();
+    *bounds = WorldBounds::default();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::PhysicsConfig;
+    use crate::wizard::{spawn_wizard, SpawnPoint};
+
+    // one of everything a level can have
+    const FULL_LEVEL: &str = r#"(
+        meta: (title: "everything"),
+        platforms: [(lowx: 0.0, highx: 400.0, lowy: 0.0, highy: 100.0, material: Ice)],
+        moving_platforms: [(
+            lowx: 500.0, highx: 600.0, lowy: 100.0, highy: 120.0,
+            waypoints: [(x: 700.0, y: 110.0)], signal: "lift",
+        )],
+        objects: [(lowx: 50.0, highx: 100.0, lowy: 100.0, highy: 150.0)],
+        death_zones: [(lowx: 400.0, highx: 450.0, lowy: 0.0, highy: 40.0)],
+        gravity_zones: [(lowx: 0.0, highx: 200.0, lowy: 300.0, highy: 500.0, direction: Up)],
+        enemies: [(lowx: 200.0, highx: 220.0, lowy: 100.0, highy: 120.0)],
+        keys: [(x: 300.0, y: 110.0, color: Red)],
+        doors: [(lowx: 380.0, highx: 400.0, lowy: 100.0, highy: 200.0, color: Red)],
+        switches: [(
+            lowx: 120.0, highx: 160.0, lowy: 100.0, highy: 105.0,
+            kind: PressurePlate, signal: "lift",
+        )],
+        exit: (x: 350.0, y: 110.0),
+    )"#;
+
+    #[test]
+    fn clearing_a_level_leaves_an_empty_world() {
+        let (level, _) = parse_ron_level(FULL_LEVEL).unwrap();
+        let mut world = World::new();
+        world.insert_resource(Gravity(GravityDirection::Down));
+        world.insert_resource(LevelPhysics::default());
+        world.insert_resource(Signals::default());
+        world.insert_resource(WorldBounds::from(RectData {
+            lowx: -100.0,
+            highx: 2000.0,
+            lowy: 0.0,
+            highy: 1000.0,
+        }));
+        world.insert_resource(PhysicsConfig::default());
+        world.insert_resource(SpawnPoint {
+            spawn: level.spawn,
+            gravity: GravityDirection::Up,
+        });
+
+        let mut load = Schedule::new();
+        load.add_systems(
+            (
+                move |mut commands: Commands| spawn_level(&mut commands, &level),
+                spawn_wizard,
+            )
+                .chain(),
+        );
+        load.run(&mut world);
+        assert_eq!(world.query::<&Wizard>().iter(&world).count(), 1);
+        assert_eq!(world.resource::<Gravity>().0, GravityDirection::Up);
+        assert_eq!(world.query::<&Platform>().iter(&world).count(), 3);
+        assert!(world.query::<&LevelEntity>().iter(&world).count() > 10);
+
+        let mut clear = Schedule::new();
+        clear.add_system(clear_level);
+        clear.run(&mut world);
+        assert_eq!(world.query::<&LevelEntity>().iter(&world).count(), 0);
+        assert_eq!(world.query::<&Platform>().iter(&world).count(), 0);
+        assert_eq!(world.query::<&Object>().iter(&world).count(), 0);
+        assert_eq!(world.query::<&DeathZone>().iter(&world).count(), 0);
+        assert_eq!(world.query::<&Wizard>().iter(&world).count(), 0);
+        // nothing the level spawned is left over, with or without a marker
+        assert_eq!(world.entities().len(), 0);
+        assert_eq!(world.resource::<Gravity>().0, GravityDirection::Down);
+        assert_eq!(world.resource::<WorldBounds>().highx, SCREEN_WIDTH);
     }
-    let exit = exit_query.single();
-    commands.entity(exit).despawn();
 }
@@ -0,0 +1,1128 @@
+//
+// level.rs
+//
+// level data parsing: plain data only, no bevy commands here.
+// spawning the parsed data is done in layout.rs
+//
+// two formats are supported:
+// - .ron: the level data below, serialized with serde
+// - .txt: the legacy semicolon separated format, converted to the same data on load
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum GravityDirection {
+    #[default]
+    Down,
+    Up,
+    Left,
+    Right,
+}
+
+// what a platform is made of: how it changes walking on it and bouncing off it.
+// the numbers behind each material are in physics.rs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum PlatformMaterial {
+    #[default]
+    Normal,
+    Ice,
+    Sticky,
+    Bouncy,
+}
+
+// a level's changes to the physics config in config.rs, everything left out keeps the
+// value from the file. level files don't need Some(..) around the values
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct PhysicsOverrides {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gravity: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ground_acceleration: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub air_acceleration: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ground_friction: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub air_friction: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_horizontal_velocity: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_vertical_velocity: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub object_friction: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wizard_size: Option<f32>,
+}
+
+// an axis aligned rectangle, in the same order as the level file: lowx highx lowy highy
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RectData {
+    pub lowx: f32,
+    pub highx: f32,
+    pub lowy: f32,
+    pub highy: f32,
+}
+
+// the window size, and the world size of levels that don't set their own bounds
+pub const SCREEN_WIDTH: f32 = 1280.0;
+pub const SCREEN_HEIGHT: f32 = 720.0;
+
+fn default_bounds() -> RectData {
+    RectData {
+        lowx: 0.0,
+        highx: SCREEN_WIDTH,
+        lowy: 0.0,
+        highy: SCREEN_HEIGHT,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ExitData {
+    pub x: f32,
+    pub y: f32,
+}
+
+// where the wizard appears, on load and after dying, and how fast it is moving then
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SpawnData {
+    pub x: f32,
+    pub y: f32,
+    #[serde(default)]
+    pub velocity_x: f32,
+    #[serde(default)]
+    pub velocity_y: f32,
+}
+
+// used by levels that don't define a spawn point (the old hard-coded 1280x720 window fraction)
+impl Default for SpawnData {
+    fn default() -> Self {
+        SpawnData {
+            x: SCREEN_WIDTH / 6.0,
+            y: SCREEN_HEIGHT / 5.0,
+            velocity_x: 0.0,
+            velocity_y: 0.0,
+        }
+    }
+}
+
+// a solid platform. the material changes how bodies walk on it and bounce off it
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PlatformData {
+    pub lowx: f32,
+    pub highx: f32,
+    pub lowy: f32,
+    pub highy: f32,
+    #[serde(default)]
+    pub material: PlatformMaterial,
+}
+
+// the legacy format only has plain platforms
+impl From<RectData> for PlatformData {
+    fn from(rect: RectData) -> Self {
+        PlatformData {
+            lowx: rect.lowx,
+            highx: rect.highx,
+            lowy: rect.lowy,
+            highy: rect.highy,
+            material: PlatformMaterial::Normal,
+        }
+    }
+}
+
+// how a moving platform goes through its waypoints once it reaches the last one:
+// linear stops there, ping-pong goes back the same way, loop goes on to the first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum PathMode {
+    Linear,
+    #[default]
+    PingPong,
+    Loop,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PointData {
+    pub x: f32,
+    pub y: f32,
+}
+
+// a platform that starts where its rectangle is and moves its center through the
+// waypoints, at speed pixels per second. speeds below MIN_PLATFORM_SPEED are clamped on load
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MovingPlatformData {
+    pub lowx: f32,
+    pub highx: f32,
+    pub lowy: f32,
+    pub highy: f32,
+    #[serde(default)]
+    pub material: PlatformMaterial,
+    pub waypoints: Vec<PointData>,
+    #[serde(default = "default_platform_speed")]
+    pub speed: f32,
+    #[serde(default)]
+    pub mode: PathMode,
+    // only moves while this is on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signal: Option<String>,
+}
+
+fn default_platform_speed() -> f32 {
+    100.0
+}
+
+// the slowest a moving platform can go, it has to get to its waypoints eventually
+pub const MIN_PLATFORM_SPEED: f32 = 1.0;
+
+// a movable crate. gravity_scale multiplies the pull on it (0 floats, 2 falls twice as hard),
+// mass makes it harder to push, and flip_immune ones keep the level's starting gravity.
+// negative scales and masses below MIN_MASS are clamped on load
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ObjectData {
+    pub lowx: f32,
+    pub highx: f32,
+    pub lowy: f32,
+    pub highy: f32,
+    #[serde(default = "default_one")]
+    pub gravity_scale: f32,
+    #[serde(default = "default_one")]
+    pub mass: f32,
+    #[serde(default)]
+    pub flip_immune: bool,
+}
+
+// the legacy format only has plain crates
+impl From<RectData> for ObjectData {
+    fn from(rect: RectData) -> Self {
+        ObjectData {
+            lowx: rect.lowx,
+            highx: rect.highx,
+            lowy: rect.lowy,
+            highy: rect.highy,
+            gravity_scale: 1.0,
+            mass: 1.0,
+            flip_immune: false,
+        }
+    }
+}
+
+// a region with its own gravity, overriding the level's for bodies that are fully inside.
+// strength scales the normal pull: 0 is zero-g, 2 pulls twice as hard.
+// with a signal it only works while that is on
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GravityZoneData {
+    pub lowx: f32,
+    pub highx: f32,
+    pub lowy: f32,
+    pub highy: f32,
+    #[serde(default)]
+    pub direction: GravityDirection,
+    #[serde(default = "default_one")]
+    pub strength: f32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signal: Option<String>,
+}
+
+// patrolling enemies walk back and forth on their platform, chasing ones go after the
+// wizard when it comes close and patrol otherwise
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum EnemyBehavior {
+    #[default]
+    Patrol,
+    Chase,
+}
+
+// an enemy falls with gravity like the wizard does. speed is in pixels per second
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct EnemyData {
+    pub lowx: f32,
+    pub highx: f32,
+    pub lowy: f32,
+    pub highy: f32,
+    #[serde(default)]
+    pub behavior: EnemyBehavior,
+    #[serde(default = "default_enemy_speed")]
+    pub speed: f32,
+}
+
+fn default_enemy_speed() -> f32 {
+    80.0
+}
+
+// keys open the doors of the same colour
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeyColor {
+    Red,
+    Green,
+    Blue,
+    Yellow,
+}
+
+// a key lying in the level, picked up by walking into it
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct KeyData {
+    pub x: f32,
+    pub y: f32,
+    pub color: KeyColor,
+}
+
+// a solid platform until the wizard touches it holding a key of its colour, or while
+// its signal is on. a door without a colour only opens by signal
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DoorData {
+    pub lowx: f32,
+    pub highx: f32,
+    pub lowy: f32,
+    pub highy: f32,
+    #[serde(default)]
+    pub color: Option<KeyColor>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signal: Option<String>,
+}
+
+// what makes a switch go on: pressure plates while the wizard or a crate is on them,
+// levers flip every time the wizard walks into them, buttons stay on for a while
+// after the wizard touched them
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum SwitchKind {
+    PressurePlate,
+    Lever,
+    Button { duration: f32 },
+}
+
+// switches aren't solid, bodies pass through them. a target is on while any switch
+// with its signal is on
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SwitchData {
+    pub lowx: f32,
+    pub highx: f32,
+    pub lowy: f32,
+    pub highy: f32,
+    pub kind: SwitchKind,
+    pub signal: String,
+}
+
+fn default_one() -> f32 {
+    1.0
+}
+
+// the lightest a crate can be, lighter ones would take all the speed of whatever hits them
+pub const MIN_MASS: f32 = 0.01;
+
+// the header block: title and the declared amount of platforms and objects.
+// the counts only exist in the legacy format, where they are validated against the lists
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LevelMeta {
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub platform_count: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub object_count: Option<usize>,
+}
+
+// new entity kinds get their own list here, with #[serde(default)] so older files still load
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LevelData {
+    pub meta: LevelMeta,
+    // the camera stays inside, anything that leaves it is gone
+    #[serde(default = "default_bounds")]
+    pub bounds: RectData,
+    #[serde(default)]
+    pub spawn: SpawnData,
+    #[serde(default)]
+    pub gravity: GravityDirection,
+    // tweaks to the physics config for this level only
+    #[serde(default)]
+    pub physics: PhysicsOverrides,
+    #[serde(default)]
+    pub platforms: Vec<PlatformData>,
+    #[serde(default)]
+    pub moving_platforms: Vec<MovingPlatformData>,
+    #[serde(default)]
+    pub objects: Vec<ObjectData>,
+    // spikes, lava: touching one kills the wizard
+    #[serde(default)]
+    pub death_zones: Vec<RectData>,
+    #[serde(default)]
+    pub gravity_zones: Vec<GravityZoneData>,
+    #[serde(default)]
+    pub enemies: Vec<EnemyData>,
+    #[serde(default)]
+    pub keys: Vec<KeyData>,
+    #[serde(default)]
+    pub doors: Vec<DoorData>,
+    #[serde(default)]
+    pub switches: Vec<SwitchData>,
+    pub exit: ExitData,
+}
+
+// position in the level file, both 1-based
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LevelParseErrorKind {
+    Io(String),
+    UnknownFormat(String),
+    Ron(String),
+    MissingSection(&'static str),
+    TrailingSection,
+    InvalidNumber(String),
+    InvalidCount(String),
+    UnknownHeaderField(String),
+    CountMismatch {
+        section: &'static str,
+        declared: usize,
+        found: usize,
+    },
+    WrongFieldCount {
+        section: &'static str,
+        expected: usize,
+        found: usize,
+    },
+    OutOfRange {
+        section: &'static str,
+        index: usize,
+        field: &'static str,
+        value: f32,
+        used: f32,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LevelParseError {
+    pub kind: LevelParseErrorKind,
+    pub span: Span,
+}
+
+impl fmt::Display for LevelParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LevelParseErrorKind::Io(message) => write!(f, "could not read level file: {}", message),
+            LevelParseErrorKind::UnknownFormat(extension) => write!(
+                f,
+                "unknown level format '{}' (expected .ron or .txt)",
+                extension
+            ),
+            LevelParseErrorKind::Ron(message) => write!(f, "{}", message),
+            LevelParseErrorKind::MissingSection(section) => {
+                write!(
+                    f,
+                    "missing {} section (sections are separated by ';')",
+                    section
+                )
+            }
+            LevelParseErrorKind::TrailingSection => {
+                write!(f, "unexpected data after the spawn section")
+            }
+            LevelParseErrorKind::InvalidNumber(token) => write!(f, "'{}' is not a number", token),
+            LevelParseErrorKind::InvalidCount(token) => {
+                write!(f, "'{}' is not a valid count", token)
+            }
+            LevelParseErrorKind::UnknownHeaderField(field) => write!(
+                f,
+                "unknown header field '{}' (expected 'platforms N' or 'objects N')",
+                field
+            ),
+            LevelParseErrorKind::CountMismatch {
+                section,
+                declared,
+                found,
+            } => write!(
+                f,
+                "header declares {} {}, but the level has {}",
+                declared, section, found
+            ),
+            LevelParseErrorKind::WrongFieldCount {
+                section,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{} entry needs {} numbers, found {}",
+                section, expected, found
+            ),
+            LevelParseErrorKind::OutOfRange {
+                section,
+                index,
+                field,
+                value,
+                used,
+            } => write!(
+                f,
+                "{} {} has {} {}, using {}",
+                section,
+                index + 1,
+                field,
+                value,
+                used
+            ),
+        }
+    }
+}
+
+impl fmt::Display for LevelParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // these happen before there is any text to point at, or after it became data
+        if let LevelParseErrorKind::Io(_)
+        | LevelParseErrorKind::UnknownFormat(_)
+        | LevelParseErrorKind::OutOfRange { .. } = self.kind
+        {
+            return write!(f, "{}", self.kind);
+        }
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.span.line, self.span.column, self.kind
+        )
+    }
+}
+
+impl std::error::Error for LevelParseError {}
+
+// a piece of the source text together with its byte offset in the whole file
+#[derive(Clone, Copy)]
+struct Piece<'a> {
+    text: &'a str,
+    offset: usize,
+}
+
+impl<'a> Piece<'a> {
+    fn split(self, separator: char) -> Vec<Piece<'a>> {
+        let mut pieces = Vec::new();
+        let mut start = 0;
+        for (i, c) in self.text.char_indices() {
+            if c == separator {
+                pieces.push(Piece {
+                    text: &self.text[start..i],
+                    offset: self.offset + start,
+                });
+                start = i + c.len_utf8();
+            }
+        }
+        pieces.push(Piece {
+            text: &self.text[start..],
+            offset: self.offset + start,
+        });
+        pieces
+    }
+
+    fn tokens(self) -> Vec<Piece<'a>> {
+        let mut tokens = Vec::new();
+        let mut start = None;
+        for (i, c) in self.text.char_indices() {
+            match (c.is_whitespace(), start) {
+                (false, None) => start = Some(i),
+                (true, Some(s)) => {
+                    tokens.push(Piece {
+                        text: &self.text[s..i],
+                        offset: self.offset + s,
+                    });
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(s) = start {
+            tokens.push(Piece {
+                text: &self.text[s..],
+                offset: self.offset + s,
+            });
+        }
+        tokens
+    }
+
+    // offset of the first non whitespace character, or the end of the piece
+    fn content_offset(self) -> usize {
+        let trimmed = self.text.trim_start();
+        self.offset + (self.text.len() - trimmed.len())
+    }
+}
+
+// strict turns header count mismatches into errors, lenient only reports them as warnings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Validation {
+    Strict,
+    Lenient,
+}
+
+impl Validation {
+    // catch broken level files while developing, but still try to play them in release builds
+    pub fn for_build() -> Self {
+        if cfg!(debug_assertions) {
+            Validation::Strict
+        } else {
+            Validation::Lenient
+        }
+    }
+}
+
+// a header count together with where it was declared, for error reporting
+struct DeclaredCount {
+    count: usize,
+    offset: usize,
+}
+
+struct Header {
+    title: String,
+    platforms: Option<DeclaredCount>,
+    objects: Option<DeclaredCount>,
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    validation: Validation,
+}
+
+impl<'a> Parser<'a> {
+    fn span_at(&self, offset: usize) -> Span {
+        let before = &self.source[..offset];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        Span {
+            line,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+
+    fn error(&self, kind: LevelParseErrorKind, offset: usize) -> LevelParseError {
+        LevelParseError {
+            kind,
+            span: self.span_at(offset),
+        }
+    }
+
+    fn number(&self, token: Piece) -> Result<f32, LevelParseError> {
+        token.text.parse::<f32>().map_err(|_| {
+            self.error(
+                LevelParseErrorKind::InvalidNumber(token.text.to_string()),
+                token.offset,
+            )
+        })
+    }
+
+    fn numbers(
+        &self,
+        entry: Piece,
+        section: &'static str,
+        expected: usize,
+    ) -> Result<Vec<f32>, LevelParseError> {
+        let tokens = entry.tokens();
+        if tokens.len() != expected {
+            return Err(self.error(
+                LevelParseErrorKind::WrongFieldCount {
+                    section,
+                    expected,
+                    found: tokens.len(),
+                },
+                entry.content_offset(),
+            ));
+        }
+        tokens.into_iter().map(|token| self.number(token)).collect()
+    }
+
+    // title first, then 'platforms N' and 'objects N' in any order, all comma separated
+    fn header(&self, section: Piece) -> Result<Header, LevelParseError> {
+        let mut entries = section.split(',').into_iter();
+        let title = entries
+            .next()
+            .map(|entry| entry.text.trim().to_string())
+            .unwrap_or_default();
+        let mut header = Header {
+            title,
+            platforms: None,
+            objects: None,
+        };
+        for entry in entries {
+            let tokens = entry.tokens();
+            if tokens.is_empty() {
+                continue;
+            }
+            let field = tokens[0];
+            let (name, target) = match field.text {
+                "platforms" => ("platforms", &mut header.platforms),
+                "objects" => ("objects", &mut header.objects),
+                _ => {
+                    return Err(self.error(
+                        LevelParseErrorKind::UnknownHeaderField(field.text.to_string()),
+                        field.offset,
+                    ))
+                }
+            };
+            let Some(value) = tokens.get(1).filter(|_| tokens.len() == 2) else {
+                return Err(self.error(
+                    LevelParseErrorKind::WrongFieldCount {
+                        section: name,
+                        expected: 1,
+                        found: tokens.len() - 1,
+                    },
+                    field.offset,
+                ));
+            };
+            let count = value.text.parse::<usize>().map_err(|_| {
+                self.error(
+                    LevelParseErrorKind::InvalidCount(value.text.to_string()),
+                    value.offset,
+                )
+            })?;
+            *target = Some(DeclaredCount {
+                count,
+                offset: value.offset,
+            });
+        }
+        Ok(header)
+    }
+
+    fn check_count(
+        &self,
+        declared: &Option<DeclaredCount>,
+        section: &'static str,
+        found: usize,
+        warnings: &mut Vec<LevelParseError>,
+    ) -> Result<(), LevelParseError> {
+        let Some(declared) = declared else {
+            return Ok(());
+        };
+        if declared.count == found {
+            return Ok(());
+        }
+        let error = self.error(
+            LevelParseErrorKind::CountMismatch {
+                section,
+                declared: declared.count,
+                found,
+            },
+            declared.offset,
+        );
+        match self.validation {
+            Validation::Strict => Err(error),
+            Validation::Lenient => {
+                warnings.push(error);
+                Ok(())
+            }
+        }
+    }
+
+    // optional: "x y" or "x y velocity_x velocity_y"
+    fn spawn(&self, section: Piece) -> Result<Option<SpawnData>, LevelParseError> {
+        if section.text.trim().is_empty() {
+            return Ok(None);
+        }
+        let expected = if section.tokens().len() > 2 { 4 } else { 2 };
+        let n = self.numbers(section, "spawn", expected)?;
+        Ok(Some(SpawnData {
+            x: n[0],
+            y: n[1],
+            velocity_x: n.get(2).copied().unwrap_or(0.0),
+            velocity_y: n.get(3).copied().unwrap_or(0.0),
+        }))
+    }
+
+    // a list of comma separated rectangles, or NULL for an empty list
+    fn rects(&self, section: Piece, name: &'static str) -> Result<Vec<RectData>, LevelParseError> {
+        let mut rects = Vec::new();
+        for entry in section.split(',') {
+            let text = entry.text.trim();
+            if text == "NULL" {
+                break;
+            }
+            if text.is_empty() {
+                continue;
+            }
+            let n = self.numbers(entry, name, 4)?;
+            rects.push(RectData {
+                lowx: n[0],
+                highx: n[1],
+                lowy: n[2],
+                highy: n[3],
+            });
+        }
+        Ok(rects)
+    }
+
+    fn parse(&self) -> Result<(LevelData, Vec<LevelParseError>), LevelParseError> {
+        let sections = Piece {
+            text: self.source,
+            offset: 0,
+        }
+        .split(';');
+        let end = self.source.len();
+        let section = |i: usize, name: &'static str| {
+            sections
+                .get(i)
+                .copied()
+                .ok_or_else(|| self.error(LevelParseErrorKind::MissingSection(name), end))
+        };
+
+        let header = self.header(section(0, "header")?)?;
+        let platforms = self.rects(section(1, "platforms")?, "platform")?;
+        let objects = self.rects(section(2, "objects")?, "object")?;
+        let exit = self.numbers(section(3, "exit")?, "exit", 2)?;
+        let spawn = match sections.get(4) {
+            Some(section) => self.spawn(*section)?,
+            None => None,
+        };
+
+        if let Some(extra) = sections.get(5) {
+            if !extra.text.trim().is_empty() {
+                return Err(
+                    self.error(LevelParseErrorKind::TrailingSection, extra.content_offset())
+                );
+            }
+        }
+
+        let mut warnings = Vec::new();
+        self.check_count(
+            &header.platforms,
+            "platforms",
+            platforms.len(),
+            &mut warnings,
+        )?;
+        self.check_count(&header.objects, "objects", objects.len(), &mut warnings)?;
+
+        let level = LevelData {
+            meta: LevelMeta {
+                title: header.title,
+                platform_count: header.platforms.map(|declared| declared.count),
+                object_count: header.objects.map(|declared| declared.count),
+            },
+            bounds: default_bounds(),
+            spawn: spawn.unwrap_or_default(),
+            gravity: GravityDirection::Down,
+            physics: PhysicsOverrides::default(),
+            platforms: platforms.into_iter().map(PlatformData::from).collect(),
+            moving_platforms: Vec::new(),
+            objects: objects.into_iter().map(ObjectData::from).collect(),
+            death_zones: Vec::new(),
+            gravity_zones: Vec::new(),
+            enemies: Vec::new(),
+            keys: Vec::new(),
+            doors: Vec::new(),
+            switches: Vec::new(),
+            exit: ExitData {
+                x: exit[0],
+                y: exit[1],
+            },
+        };
+        Ok((level, warnings))
+    }
+}
+
+// legacy .txt format.
+// returns the level and, in lenient mode, the validation problems that were let through
+pub fn parse_level(
+    source: &str,
+    validation: Validation,
+) -> Result<(LevelData, Vec<LevelParseError>), LevelParseError> {
+    Parser { source, validation }.parse()
+}
+
+// optional values can be written without Some(..), e.g. `physics: (gravity: 400.0)`.
+// returns the level and the values that had to be clamped to make sense
+pub fn parse_ron_level(source: &str) -> Result<(LevelData, Vec<LevelParseError>), LevelParseError> {
+    let options =
+        ron::Options::default().with_default_extension(ron::extensions::Extensions::IMPLICIT_SOME);
+    let mut level: LevelData = options.from_str(source).map_err(|e| LevelParseError {
+        kind: LevelParseErrorKind::Ron(e.code.to_string()),
+        span: Span {
+            line: e.position.line,
+            column: e.position.col,
+        },
+    })?;
+    let warnings = clamp_values(&mut level);
+    Ok((level, warnings))
+}
+
+// crates can float but not fall upwards, and need some mass.
+// moving platforms have to move forwards
+fn clamp_values(level: &mut LevelData) -> Vec<LevelParseError> {
+    let mut warnings = Vec::new();
+    let mut clamp =
+        |section: &'static str, index: usize, field: &'static str, value: &mut f32, min: f32| {
+            if value.is_nan() || *value < min {
+                warnings.push(LevelParseError {
+                    kind: LevelParseErrorKind::OutOfRange {
+                        section,
+                        index,
+                        field,
+                        value: *value,
+                        used: min,
+                    },
+                    span: Span { line: 0, column: 0 },
+                });
+                *value = min;
+            }
+        };
+    for (index, object) in level.objects.iter_mut().enumerate() {
+        clamp(
+            "object",
+            index,
+            "gravity_scale",
+            &mut object.gravity_scale,
+            0.0,
+        );
+        clamp("object", index, "mass", &mut object.mass, MIN_MASS);
+    }
+    for (index, platform) in level.moving_platforms.iter_mut().enumerate() {
+        clamp(
+            "moving platform",
+            index,
+            "speed",
+            &mut platform.speed,
+            MIN_PLATFORM_SPEED,
+        );
+    }
+    warnings
+}
+
+pub fn level_to_ron(level: &LevelData) -> String {
+    let config = ron::ser::PrettyConfig::new().struct_names(false);
+    ron::ser::to_string_pretty(level, config).expect("level data is always serializable")
+}
+
+fn io_error(error: std::io::Error) -> LevelParseError {
+    LevelParseError {
+        kind: LevelParseErrorKind::Io(error.to_string()),
+        span: Span { line: 0, column: 0 },
+    }
+}
+
+// picks the format from the file extension.
+// validation only applies to the legacy format, .ron files only warn about clamped values
+pub fn read_level_file(
+    path: &str,
+    validation: Validation,
+) -> Result<(LevelData, Vec<LevelParseError>), LevelParseError> {
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default();
+    if extension != "ron" && extension != "txt" {
+        return Err(LevelParseError {
+            kind: LevelParseErrorKind::UnknownFormat(extension.to_string()),
+            span: Span { line: 0, column: 0 },
+        });
+    }
+    let source = fs::read_to_string(path).map_err(io_error)?;
+    if extension == "ron" {
+        parse_ron_level(&source)
+    } else {
+        parse_level(&source, validation)
+    }
+}
+
+// converts a legacy .txt level into a .ron level
+pub fn convert_level_file(
+    from: &str,
+    to: &str,
+    validation: Validation,
+) -> Result<Vec<LevelParseError>, LevelParseError> {
+    let (level, warnings) = read_level_file(from, validation)?;
+    fs::write(to, level_to_ron(&level)).map_err(io_error)?;
+    Ok(warnings)
+}
+
+// every .ron and .txt level in a directory, in play order: numbered files first, by number
+pub fn find_level_files(dir: &str) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut levels: Vec<(Option<u32>, String)> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            matches!(
+                path.extension().and_then(|extension| extension.to_str()),
+                Some("ron") | Some("txt")
+            )
+        })
+        .map(|path| {
+            let number = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse::<u32>().ok());
+            (number, path.to_string_lossy().into_owned())
+        })
+        .collect();
+    levels.sort_by(|a, b| match (a.0, b.0) {
+        (Some(x), Some(y)) => x.cmp(&y).then_with(|| a.1.cmp(&b.1)),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => a.1.cmp(&b.1),
+    });
+    levels.into_iter().map(|(_, path)| path).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error(source: &str) -> LevelParseError {
+        parse_level(source, Validation::Strict).expect_err("level should not parse")
+    }
+
+    #[test]
+    fn parses_the_first_level() {
+        let (level, warnings) =
+            parse_level(include_str!("../levels/1.txt"), Validation::Strict).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(level.meta.title, "LEVEL 1");
+        assert_eq!(level.meta.platform_count, Some(6));
+        assert_eq!(level.meta.object_count, Some(1));
+        assert_eq!(level.platforms.len(), 6);
+        assert_eq!(
+            level.platforms[1],
+            PlatformData::from(RectData {
+                lowx: 450.0,
+                highx: 600.0,
+                lowy: 500.0,
+                highy: 550.0,
+            })
+        );
+        assert_eq!(level.objects.len(), 1);
+        assert_eq!(level.objects[0].lowx, 50.0);
+        assert_eq!(level.exit, ExitData { x: 460.0, y: 560.0 });
+        assert_eq!(level.spawn.x, 213.0);
+        assert_eq!(level.spawn.y, 144.0);
+    }
+
+    #[test]
+    fn every_bundled_level_loads() {
+        let levels = find_level_files("levels");
+        assert!(levels.len() >= 3);
+        for path in levels {
+            if let Err(error) = read_level_file(&path, Validation::Strict) {
+                panic!("{}: {}", path, error);
+            }
+        }
+    }
+
+    #[test]
+    fn reports_a_bad_number_where_it_is() {
+        let error = parse_error("T;\n0 1 x 3;NULL;1 2");
+        assert_eq!(
+            error.kind,
+            LevelParseErrorKind::InvalidNumber("x".to_string())
+        );
+        assert_eq!(error.span, Span { line: 2, column: 5 });
+    }
+
+    #[test]
+    fn reports_a_wrong_field_count_at_the_entry() {
+        let error = parse_error("T;\n0 1 2 3,\n  4 5 6;NULL;1 2");
+        assert_eq!(
+            error.kind,
+            LevelParseErrorKind::WrongFieldCount {
+                section: "platform",
+                expected: 4,
+                found: 3,
+            }
+        );
+        assert_eq!(error.span, Span { line: 3, column: 3 });
+
+        let error = parse_error("T;NULL;NULL;1 2 3");
+        assert_eq!(
+            error.kind,
+            LevelParseErrorKind::WrongFieldCount {
+                section: "exit",
+                expected: 2,
+                found: 3,
+            }
+        );
+        assert_eq!(
+            error.span,
+            Span {
+                line: 1,
+                column: 13
+            }
+        );
+    }
+
+    #[test]
+    fn reports_count_mismatches_at_the_declared_count() {
+        let source = "T, platforms 2;0 1 2 3;NULL;1 2";
+        let error = parse_error(source);
+        assert_eq!(
+            error.kind,
+            LevelParseErrorKind::CountMismatch {
+                section: "platforms",
+                declared: 2,
+                found: 1,
+            }
+        );
+        assert_eq!(
+            error.span,
+            Span {
+                line: 1,
+                column: 14
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "line 1, column 14: header declares 2 platforms, but the level has 1"
+        );
+
+        // lenient keeps the level and hands the same problem back as a warning
+        let (level, warnings) = parse_level(source, Validation::Lenient).unwrap();
+        assert_eq!(level.platforms.len(), 1);
+        assert_eq!(warnings, vec![error]);
+    }
+
+    #[test]
+    fn clamps_crates_that_make_no_sense() {
+        let (level, warnings) = parse_ron_level(
+            r#"(
+                meta: (title: "T"),
+                objects: [
+                    (lowx: 0.0, highx: 1.0, lowy: 0.0, highy: 1.0, gravity_scale: 0.0, mass: 2.0),
+                    (lowx: 0.0, highx: 1.0, lowy: 0.0, highy: 1.0, gravity_scale: -1.0, mass: 0.0),
+                ],
+                exit: (x: 0.0, y: 0.0),
+            )"#,
+        )
+        .unwrap();
+        assert_eq!(level.objects[0].gravity_scale, 0.0);
+        assert_eq!(level.objects[0].mass, 2.0);
+        assert_eq!(level.objects[1].gravity_scale, 0.0);
+        assert_eq!(level.objects[1].mass, MIN_MASS);
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(
+            warnings[0].to_string(),
+            "object 2 has gravity_scale -1, using 0"
+        );
+    }
+
+    #[test]
+    fn clamps_platforms_that_would_never_arrive() {
+        let (level, warnings) = parse_ron_level(
+            r#"(
+                meta: (title: "T"),
+                moving_platforms: [
+                    (lowx: 0.0, highx: 1.0, lowy: 0.0, highy: 1.0, waypoints: [(x: 0.0, y: 9.0)]),
+                    (lowx: 0.0, highx: 1.0, lowy: 0.0, highy: 1.0, waypoints: [], speed: -50.0),
+                    (lowx: 0.0, highx: 1.0, lowy: 0.0, highy: 1.0, waypoints: [], speed: 0.0),
+                ],
+                exit: (x: 0.0, y: 0.0),
+            )"#,
+        )
+        .unwrap();
+        assert_eq!(level.moving_platforms[0].speed, 100.0);
+        assert_eq!(level.moving_platforms[1].speed, MIN_PLATFORM_SPEED);
+        assert_eq!(level.moving_platforms[2].speed, MIN_PLATFORM_SPEED);
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(
+            warnings[0].to_string(),
+            "moving platform 2 has speed -50, using 1"
+        );
+    }
+
+    #[test]
+    fn reports_missing_sections_at_the_end() {
+        let error = parse_error("T;\nNULL");
+        assert_eq!(error.kind, LevelParseErrorKind::MissingSection("objects"));
+        assert_eq!(error.span, Span { line: 2, column: 5 });
+    }
+}
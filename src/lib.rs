@@ -0,0 +1,17 @@
+// lib.rs
+// Project: gravity_wizard
+// Author: slamgLuke
+// The game itself, shared by the game binary in main.rs and the benchmarks.
+
+pub mod broadphase;
+pub mod camera;
+pub mod campaign;
+pub mod config;
+pub mod debug;
+pub mod enemy;
+pub mod layout;
+pub mod level;
+pub mod menu;
+pub mod moving_platform;
+pub mod physics;
+pub mod wizard;
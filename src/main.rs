@@ -3,46 +3,177 @@
 // Author: slamgLuke
 // A Bevy game.
 
-mod debug;
-mod layout;
-mod physics;
-mod wizard;
-
-use crate::debug::*;
-use crate::layout::*;
-use crate::physics::*;
-use crate::wizard::*;
 use bevy::{
     prelude::*,
+    transform::TransformSystem,
     window::{PrimaryWindow, WindowMode, WindowResolution},
 };
+use gravity_wizard::broadphase::*;
+use gravity_wizard::camera::*;
+use gravity_wizard::campaign::*;
+use gravity_wizard::config::*;
+use gravity_wizard::debug::*;
+use gravity_wizard::enemy::*;
+use gravity_wizard::layout::*;
+use gravity_wizard::level::*;
+use gravity_wizard::menu::*;
+use gravity_wizard::moving_platform::*;
+use gravity_wizard::physics::*;
+use gravity_wizard::wizard::*;
 
 fn main() {
+    // `gravity_wizard convert levels/1.txt levels/1.ron` turns a legacy level into the ron format
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() == 4 && args[1] == "convert" {
+        match convert_level_file(&args[2], &args[3], Validation::Lenient) {
+            Ok(warnings) => {
+                for warning in warnings {
+                    eprintln!("{}: warning: {}", args[2], warning);
+                }
+                println!("Converted {} to {}", args[2], args[3]);
+            }
+            Err(error) => eprintln!("{}: {}", args[2], error),
+        }
+        return;
+    }
+
     println!("Running Bevy!");
+    let physics_config = PhysicsConfigFile::load(PHYSICS_CONFIG_FILE);
     App::new()
         .insert_resource(Gravity(GravityDirection::Down))
+        .insert_resource(FixedTime::new_from_secs(PHYSICS_TIMESTEP))
+        .insert_resource(physics_config.config)
+        .insert_resource(physics_config)
+        .init_resource::<LevelPhysics>()
+        .init_resource::<SpawnPoint>()
+        .init_resource::<WorldBounds>()
+        .init_resource::<PlatformGrid>()
+        .insert_resource(Campaign::from_dir(LEVELS_DIR))
+        .insert_resource(Progress::load(PROGRESS_FILE))
+        .init_resource::<LevelTimer>()
+        .init_resource::<Deaths>()
+        .init_resource::<Signals>()
+        .add_event::<WizardDied>()
+        .add_event::<SignalChanged>()
         .add_plugins(DefaultPlugins)
-        .add_startup_systems((window_setup, debug_grid, load_level, spawn_wizard))
+        .add_state::<AppState>()
+        .add_startup_systems((window_setup, debug_grid))
+        .add_system(update_physics_config)
+        // main menu
+        .add_systems((clear_level, spawn_main_menu).in_schedule(OnEnter(AppState::MainMenu)))
+        .add_system(main_menu_input.in_set(OnUpdate(AppState::MainMenu)))
+        .add_system(despawn_screen::<MainMenuScreen>.in_schedule(OnExit(AppState::MainMenu)))
+        // level select
+        .add_system(spawn_level_select.in_schedule(OnEnter(AppState::LevelSelect)))
+        .add_systems(
+            (level_select_input, highlight_level_select)
+                .chain()
+                .in_set(OnUpdate(AppState::LevelSelect)),
+        )
+        .add_system(despawn_screen::<LevelSelectScreen>.in_schedule(OnExit(AppState::LevelSelect)))
+        // playing: coming back from the pause screen keeps the loaded level
+        .add_systems(
+            (
+                reset_level_timer.run_if(not(level_loaded)),
+                reset_deaths.run_if(not(level_loaded)),
+                load_level.run_if(not(level_loaded)),
+                // the wizard needs the spawn point inserted by load_level
+                apply_system_buffers,
+                // a level that failed to load has nowhere to spawn the wizard
+                spawn_wizard
+                    .run_if(level_loaded)
+                    .run_if(not(wizard_spawned)),
+            )
+                .chain()
+                .in_schedule(OnEnter(AppState::Playing)),
+        )
         .add_systems(
             (
+                restore_physics_position,
+                body_gravity,
                 wizard_input,
+                enemy_ai,
+                object_friction,
                 gravitational_pull,
-                platform_collision,
-                object_collision,
+                push_objects,
+                update_platform_grid,
                 apply_movement,
+                move_platforms,
+                store_physics_position,
+            )
+                .chain()
+                .distributive_run_if(in_state(AppState::Playing))
+                .in_schedule(CoreSchedule::FixedUpdate),
+        )
+        .add_system(
+            interpolate_transforms
+                .run_if(in_state(AppState::Playing))
+                .in_base_set(CoreSet::PostUpdate)
+                .before(TransformSystem::TransformPropagate),
+        )
+        .add_system(
+            camera_follow
+                .run_if(in_state(AppState::Playing))
+                .in_base_set(CoreSet::PostUpdate)
+                .after(interpolate_transforms)
+                .before(TransformSystem::TransformPropagate),
+        )
+        .add_systems(
+            (
+                set_active_color.run_if(not(in_air)),
+                set_passive_color.run_if(in_air),
+                flip_gravity.run_if(not(in_air)),
+                finish_level.run_if(is_in_exit),
+                pick_up_keys,
+                unlock_doors.after(pick_up_keys),
+                update_switches,
+                apply_signals.after(update_switches),
+                pause_input,
+                tick_level_timer,
                 debug_wizard,
+                check_wizard_death,
+                kill_enemies,
+                resize_wizard
+                    .run_if(resource_changed::<PhysicsConfig>())
+                    .after(update_physics_config),
             )
-                .chain(),
+                .in_set(OnUpdate(AppState::Playing)),
         )
-        .add_system(set_active_color.run_if(not(in_air)))
-        .add_system(set_passive_color.run_if(in_air))
-        .add_system(flip_gravity.run_if(not(in_air)))
         .add_systems(
-            (despawn_wizard, spawn_wizard)
+            (
+                count_death,
+                drop_keys,
+                despawn_wizard,
+                spawn_wizard,
+                update_death_counter,
+            )
                 .chain()
-                .distributive_run_if(out_of_screen),
+                .distributive_run_if(on_event::<WizardDied>())
+                .after(check_wizard_death)
+                .in_set(OnUpdate(AppState::Playing)),
         )
-        .add_system(clear_level.run_if(is_in_exit))
+        // paused
+        .add_system(spawn_pause_screen.in_schedule(OnEnter(AppState::Paused)))
+        .add_system(paused_input.in_set(OnUpdate(AppState::Paused)))
+        .add_system(despawn_screen::<PauseScreen>.in_schedule(OnExit(AppState::Paused)))
+        // level complete
+        .add_systems(
+            (
+                clear_level,
+                record_level_time.before(advance_campaign),
+                advance_campaign,
+                spawn_level_complete_screen,
+            )
+                .in_schedule(OnEnter(AppState::LevelComplete)),
+        )
+        .add_system(level_complete_countdown.in_set(OnUpdate(AppState::LevelComplete)))
+        .add_system(
+            despawn_screen::<LevelCompleteScreen>.in_schedule(OnExit(AppState::LevelComplete)),
+        )
+        // game over: the whole campaign is beaten
+        .add_system(show_campaign_complete.in_schedule(OnEnter(AppState::GameOver)))
+        .add_system(game_over_input.in_set(OnUpdate(AppState::GameOver)))
+        .add_system(despawn_screen::<CampaignCompleteText>.in_schedule(OnExit(AppState::GameOver)))
         .run();
 }
 
@@ -52,16 +183,51 @@ pub fn window_setup(
 ) {
     let mut window = window_query.single_mut();
     window.resizable = false;
-    window.resolution = WindowResolution::new(1280.0, 720.0);
+    window.resolution = WindowResolution::new(SCREEN_WIDTH, SCREEN_HEIGHT);
     window.mode = WindowMode::Windowed;
     window.title = "Gravity Wizard".to_string();
 
-    commands.spawn(Camera2dBundle {
-        transform: Transform::from_xyz(window.width() / 2.0, window.height() / 2.0, 0.0),
-        ..default()
-    });
+    let center = Vec2::new(window.width(), window.height()) / 2.0;
+    commands.spawn((
+        Camera2dBundle {
+            transform: Transform::from_xyz(center.x, center.y, 0.0),
+            ..default()
+        },
+        FollowCamera { focus: center },
+    ));
 }
 
-pub fn load_level(commands: Commands) {
-    level_data_reader("levels/1.txt".into(), commands);
+pub fn load_level(
+    mut commands: Commands,
+    mut window_query: Query<&mut Window, With<PrimaryWindow>>,
+    campaign: Res<Campaign>,
+    config_file: Res<PhysicsConfigFile>,
+    mut config: ResMut<PhysicsConfig>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let Some(path) = campaign.current_level() else {
+        return;
+    };
+    match read_level_file(path, Validation::for_build()) {
+        Ok((level, warnings)) => {
+            for warning in warnings {
+                eprintln!("{}: warning: {}", path, warning);
+            }
+            window_query.single_mut().title = format!("Gravity Wizard - {}", level.meta.title);
+            commands.insert_resource(SpawnPoint {
+                spawn: level.spawn,
+                gravity: level.gravity,
+            });
+            // right away, the wizard is spawned with the level's config
+            *config = config_file.config.with_overrides(&level.physics);
+            commands.insert_resource(LevelPhysics(level.physics));
+            commands.insert_resource(WorldBounds::from(level.bounds));
+            spawn_level(&mut commands, &level);
+        }
+        Err(error) => {
+            // nothing to play, the level select shows the level as broken
+            eprintln!("{}: {}", path, error);
+            next_state.set(AppState::LevelSelect);
+        }
+    }
 }
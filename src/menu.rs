@@ -0,0 +1,307 @@
+//
+// menu.rs
+//
+// game flow: which screen is shown and which systems run
+use crate::campaign::*;
+use crate::level::*;
+use bevy::{app::AppExit, prelude::*};
+
+#[derive(States, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum AppState {
+    #[default]
+    MainMenu,
+    LevelSelect,
+    Playing,
+    Paused,
+    LevelComplete,
+    GameOver,
+}
+
+// time the "level complete" screen stays up before the next level is loaded
+pub const LEVEL_COMPLETE_DELAY: f32 = 1.5;
+
+#[derive(Resource)]
+pub struct LevelCompleteTimer(pub Timer);
+
+// markers for everything spawned by a screen, so it can be despawned on exit
+#[derive(Component)]
+pub struct MainMenuScreen;
+
+#[derive(Component)]
+pub struct LevelSelectScreen;
+
+#[derive(Component)]
+pub struct PauseScreen;
+
+#[derive(Component)]
+pub struct LevelCompleteScreen;
+
+pub fn despawn_screen<T: Component>(mut commands: Commands, query: Query<Entity, With<T>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+// a full width column in the middle of the screen, for the lines of text of a screen
+fn screen_column() -> NodeBundle {
+    NodeBundle {
+        style: Style {
+            size: Size::width(Val::Percent(100.0)),
+            position_type: PositionType::Absolute,
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::Center,
+            margin: UiRect::vertical(Val::Auto),
+            ..default()
+        },
+        ..default()
+    }
+}
+
+// a centered column of text lines, the first one bigger
+pub fn spawn_text_screen(commands: &mut Commands, marker: impl Component, lines: &[&str]) {
+    commands
+        .spawn((screen_column(), marker))
+        .with_children(|parent| {
+            for (i, line) in lines.iter().enumerate() {
+                parent.spawn(TextBundle::from_section(
+                    *line,
+                    TextStyle {
+                        font_size: if i == 0 { 64.0 } else { 28.0 },
+                        color: Color::WHITE,
+                        ..default()
+                    },
+                ));
+            }
+        });
+}
+
+// main menu
+pub fn spawn_main_menu(mut commands: Commands) {
+    spawn_text_screen(
+        &mut commands,
+        MainMenuScreen,
+        &[
+            "Gravity Wizard",
+            "Enter: play",
+            "L: level select",
+            "Esc: quit",
+        ],
+    );
+}
+
+pub fn main_menu_input(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut campaign: ResMut<Campaign>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Return) {
+        // a finished campaign starts over
+        if campaign.is_complete() {
+            campaign.current = 0;
+        }
+        next_state.set(AppState::Playing);
+    } else if keyboard_input.just_pressed(KeyCode::L) {
+        next_state.set(AppState::LevelSelect);
+    } else if keyboard_input.just_pressed(KeyCode::Escape) {
+        exit.send(AppExit);
+    }
+}
+
+// level select
+pub const LEVEL_SELECT_COLOR: Color = Color::WHITE;
+pub const LEVEL_SELECT_COLOR_SELECTED: Color = Color::rgb(1.0, 1.0, 0.0);
+pub const LEVEL_SELECT_COLOR_LOCKED: Color = Color::GRAY;
+
+#[derive(Resource, Default)]
+pub struct LevelSelect {
+    pub selected: usize,
+    // levels that failed to load, they are listed but can't be played
+    pub broken: Vec<bool>,
+}
+
+impl LevelSelect {
+    pub fn is_playable(&self, index: usize, progress: &Progress) -> bool {
+        progress.is_unlocked(index) && !self.broken.get(index).copied().unwrap_or(true)
+    }
+}
+
+// one line of the level list, with its index in the campaign
+#[derive(Component)]
+pub struct LevelSelectEntry(pub usize);
+
+pub fn spawn_level_select(
+    mut commands: Commands,
+    campaign: Res<Campaign>,
+    progress: Res<Progress>,
+) {
+    let selected = campaign
+        .current
+        .min(campaign.levels.len().saturating_sub(1));
+    // the same validation as loading the level to play it, so listed levels really load
+    let levels: Vec<_> = campaign
+        .levels
+        .iter()
+        .map(|path| (path, read_level_file(path, Validation::for_build())))
+        .collect();
+    commands.insert_resource(LevelSelect {
+        selected,
+        broken: levels.iter().map(|(_, level)| level.is_err()).collect(),
+    });
+
+    let style = |font_size: f32| TextStyle {
+        font_size,
+        color: LEVEL_SELECT_COLOR,
+        ..default()
+    };
+    commands
+        .spawn((screen_column(), LevelSelectScreen))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section("Level select", style(64.0)));
+            for (i, (path, level)) in levels.into_iter().enumerate() {
+                // the title comes from the level itself, broken levels still get listed
+                let title = match level {
+                    Ok((level, _)) => level.meta.title,
+                    Err(error) => format!("{} (broken: {})", path, error),
+                };
+                let status = if !progress.is_unlocked(i) {
+                    "locked".to_string()
+                } else if let Some(time) = progress.best_time(path) {
+                    format!("best {:.2}s", time)
+                } else {
+                    "unlocked".to_string()
+                };
+                parent.spawn((
+                    TextBundle::from_section(
+                        format!("{}. {} - {}", i + 1, title, status),
+                        style(28.0),
+                    ),
+                    LevelSelectEntry(i),
+                ));
+            }
+            parent.spawn(TextBundle::from_section(
+                "Up/Down: choose, Enter: play, Esc: back",
+                style(20.0),
+            ));
+        });
+}
+
+pub fn level_select_input(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut level_select: ResMut<LevelSelect>,
+    mut campaign: ResMut<Campaign>,
+    progress: Res<Progress>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let last = campaign.levels.len().saturating_sub(1);
+    if keyboard_input.just_pressed(KeyCode::Up) || keyboard_input.just_pressed(KeyCode::W) {
+        level_select.selected = level_select.selected.saturating_sub(1);
+    } else if keyboard_input.just_pressed(KeyCode::Down) || keyboard_input.just_pressed(KeyCode::S)
+    {
+        level_select.selected = (level_select.selected + 1).min(last);
+    } else if keyboard_input.just_pressed(KeyCode::Return) {
+        if level_select.is_playable(level_select.selected, &progress) {
+            campaign.current = level_select.selected;
+            next_state.set(AppState::Playing);
+        }
+    } else if keyboard_input.just_pressed(KeyCode::Escape) {
+        next_state.set(AppState::MainMenu);
+    }
+}
+
+pub fn highlight_level_select(
+    level_select: Res<LevelSelect>,
+    progress: Res<Progress>,
+    mut entry_query: Query<(&mut Text, &LevelSelectEntry)>,
+) {
+    for (mut text, entry) in entry_query.iter_mut() {
+        text.sections[0].style.color = if entry.0 == level_select.selected {
+            LEVEL_SELECT_COLOR_SELECTED
+        } else if level_select.is_playable(entry.0, &progress) {
+            LEVEL_SELECT_COLOR
+        } else {
+            LEVEL_SELECT_COLOR_LOCKED
+        };
+    }
+}
+
+// pause
+pub fn pause_input(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Escape) || keyboard_input.just_pressed(KeyCode::P) {
+        next_state.set(AppState::Paused);
+    }
+}
+
+pub fn spawn_pause_screen(mut commands: Commands) {
+    spawn_text_screen(
+        &mut commands,
+        PauseScreen,
+        &["Paused", "Esc: resume", "Q: quit to menu"],
+    );
+}
+
+pub fn paused_input(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Escape) || keyboard_input.just_pressed(KeyCode::P) {
+        next_state.set(AppState::Playing);
+    } else if keyboard_input.just_pressed(KeyCode::Q) {
+        next_state.set(AppState::MainMenu);
+    }
+}
+
+// level complete
+pub fn finish_level(mut next_state: ResMut<NextState<AppState>>) {
+    next_state.set(AppState::LevelComplete);
+}
+
+pub fn spawn_level_complete_screen(
+    mut commands: Commands,
+    level_timer: Res<LevelTimer>,
+    deaths: Res<Deaths>,
+) {
+    commands.insert_resource(LevelCompleteTimer(Timer::from_seconds(
+        LEVEL_COMPLETE_DELAY,
+        TimerMode::Once,
+    )));
+    spawn_text_screen(
+        &mut commands,
+        LevelCompleteScreen,
+        &[
+            "Level complete!",
+            &format!("Time: {:.2}s", level_timer.0),
+            &format!("Deaths: {}", deaths.0),
+        ],
+    );
+}
+
+// waits for the timer, then loads the next level or ends the game after the last one
+pub fn level_complete_countdown(
+    time: Res<Time>,
+    mut timer: ResMut<LevelCompleteTimer>,
+    campaign: Res<Campaign>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if timer.0.tick(time.delta()).just_finished() {
+        if campaign.is_complete() {
+            next_state.set(AppState::GameOver);
+        } else {
+            next_state.set(AppState::Playing);
+        }
+    }
+}
+
+// game over: shown after the last level of the campaign
+pub fn game_over_input(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Return) || keyboard_input.just_pressed(KeyCode::Escape)
+    {
+        next_state.set(AppState::MainMenu);
+    }
+}
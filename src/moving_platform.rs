@@ -0,0 +1,109 @@
+//
+// moving_platform.rs
+//
+// platforms that follow a path of waypoints. they are moved by the path, not by forces,
+// and push and carry the bodies around them (see apply_movement)
+use crate::layout::*;
+use crate::level::PathMode;
+use crate::physics::*;
+use bevy::prelude::*;
+
+#[derive(Component)]
+pub struct PlatformPath {
+    // positions of the platform's center, the first one is where it started
+    pub points: Vec<Vec2>,
+    pub mode: PathMode,
+    pub speed: f32,
+    // the point it is heading to
+    pub next: usize,
+    // ping-pong on the way back
+    pub reversed: bool,
+    // how it moves during the next physics step, collision sweeps bodies against it
+    pub velocity: Vec2,
+    // platforms waiting for a signal stand still until it comes
+    pub active: bool,
+}
+
+impl PlatformPath {
+    pub fn new(points: Vec<Vec2>, mode: PathMode, speed: f32) -> Self {
+        PlatformPath {
+            next: 1.min(points.len() - 1),
+            points,
+            mode,
+            speed,
+            reversed: false,
+            velocity: Vec2::ZERO,
+            active: true,
+        }
+    }
+
+    // the point after next, or None at the end of a linear path
+    fn advance(&self) -> Option<(usize, bool)> {
+        let last = self.points.len() - 1;
+        match self.mode {
+            PathMode::Linear => (self.next < last).then_some((self.next + 1, false)),
+            PathMode::Loop => Some(((self.next + 1) % self.points.len(), false)),
+            PathMode::PingPong if self.reversed => Some(if self.next == 0 {
+                (1.min(last), false)
+            } else {
+                (self.next - 1, true)
+            }),
+            PathMode::PingPong => Some(if self.next == last {
+                (last.saturating_sub(1), true)
+            } else {
+                (self.next + 1, false)
+            }),
+        }
+    }
+
+    // the velocity that takes the platform from center towards the next point, without
+    // overshooting it. moves on to the point after once it's there
+    fn plan(&mut self, center: Vec2, dt: f32) -> Vec2 {
+        // a path of one point, or a loop of points on top of each other, goes nowhere
+        for _ in 0..self.points.len() {
+            let offset = self.points[self.next] - center;
+            if offset.length() > f32::EPSILON {
+                return if offset.length() <= self.speed * dt {
+                    offset / dt
+                } else {
+                    offset.normalize() * self.speed
+                };
+            }
+            let Some((next, reversed)) = self.advance() else {
+                break;
+            };
+            self.next = next;
+            self.reversed = reversed;
+        }
+        Vec2::ZERO
+    }
+}
+
+// moves the platforms by the velocity the bodies were just moved against, keeps their
+// bounds on their transform, and plans the next step
+pub fn move_platforms(
+    mut platform_query: Query<(&mut PlatformPath, &mut Platform, &mut Transform)>,
+    fixed_time: Res<FixedTime>,
+) {
+    let dt = physics_dt(&fixed_time);
+    for (mut path, mut platform, mut transform) in platform_query.iter_mut() {
+        if path.velocity != Vec2::ZERO {
+            let delta = path.velocity * dt;
+            transform.translation.x += delta.x;
+            transform.translation.y += delta.y;
+            let half = Vec2::new(
+                platform.highx - platform.lowx,
+                platform.highy - platform.lowy,
+            ) / 2.0;
+            platform.lowx = transform.translation.x - half.x;
+            platform.highx = transform.translation.x + half.x;
+            platform.lowy = transform.translation.y - half.y;
+            platform.highy = transform.translation.y + half.y;
+        }
+        path.velocity = if path.active {
+            path.plan(transform.translation.truncate(), dt)
+        } else {
+            Vec2::ZERO
+        };
+    }
+}
@@ -1,22 +1,126 @@
 //
 // physics.rs
 //
+use crate::broadphase::*;
+use crate::config::*;
 use crate::layout::*;
 use bevy::prelude::*;
 
-pub enum GravityDirection {
-    Down,
-    Up,
+// the plain data lives with the level format, how it behaves is defined here
+pub use crate::level::{GravityDirection, PlatformMaterial};
+
+impl GravityDirection {
+    // unit vector gravity pulls along
+    pub fn down(self) -> Vec2 {
+        match self {
+            GravityDirection::Down => Vec2::NEG_Y,
+            GravityDirection::Up => Vec2::Y,
+            GravityDirection::Left => Vec2::NEG_X,
+            GravityDirection::Right => Vec2::X,
+        }
+    }
+
+    // the positive direction of the axis bodies walk and slide along, perpendicular to
+    // gravity. on floors and ceilings that's right, on walls it's up
+    pub fn right(self) -> Vec2 {
+        match self {
+            GravityDirection::Down | GravityDirection::Up => Vec2::X,
+            GravityDirection::Left | GravityDirection::Right => Vec2::Y,
+        }
+    }
+
+    pub fn opposite(self) -> Self {
+        match self {
+            GravityDirection::Down => GravityDirection::Up,
+            GravityDirection::Up => GravityDirection::Down,
+            GravityDirection::Left => GravityDirection::Right,
+            GravityDirection::Right => GravityDirection::Left,
+        }
+    }
 }
 
-pub const GRAVITY: f32 = 9.8 * 1.5;
+// hits slower than this don't bounce, so bodies can come to rest on bouncy platforms
+pub const MIN_BOUNCE_SPEED: f32 = 60.0;
+
+impl PlatformMaterial {
+    // multiplies the ground friction
+    pub fn friction(self) -> f32 {
+        match self {
+            PlatformMaterial::Ice => 0.05,
+            PlatformMaterial::Sticky => 4.0,
+            PlatformMaterial::Normal | PlatformMaterial::Bouncy => 1.0,
+        }
+    }
+
+    // multiplies the ground acceleration
+    pub fn acceleration(self) -> f32 {
+        match self {
+            PlatformMaterial::Ice => 0.25,
+            PlatformMaterial::Normal | PlatformMaterial::Sticky | PlatformMaterial::Bouncy => 1.0,
+        }
+    }
+
+    // multiplies the max walking speed
+    pub fn max_speed(self) -> f32 {
+        match self {
+            PlatformMaterial::Sticky => 0.4,
+            PlatformMaterial::Normal | PlatformMaterial::Ice | PlatformMaterial::Bouncy => 1.0,
+        }
+    }
+
+    // how much of the impact speed a body keeps when it bounces off
+    pub fn restitution(self) -> f32 {
+        match self {
+            PlatformMaterial::Bouncy => 0.8,
+            PlatformMaterial::Normal | PlatformMaterial::Ice | PlatformMaterial::Sticky => 0.0,
+        }
+    }
+}
+
+// the velocity component along an axis, which is either x or y
+pub fn velocity_along(velocity: &mut Velocity, axis: Vec2) -> &mut f32 {
+    if axis.x != 0.0 {
+        &mut velocity.x
+    } else {
+        &mut velocity.y
+    }
+}
+
+// physics runs at a fixed rate, so the same inputs give the same trajectory on any machine
+pub const PHYSICS_TIMESTEP: f32 = 1.0 / 60.0;
 
 #[derive(Resource)]
 pub struct Gravity(pub GravityDirection);
 
-pub const HORIZONTAL_ACCELERATION: f32 = 4.0;
-pub const MAX_HORIZONTAL_VELOCITY: f32 = 250.0;
-pub const MAX_VERTICAL_VELOCITY: f32 = 980.0;
+// the gravity acting on one body: the level's, or that of the gravity zone it is in.
+// kept up to date by body_gravity every physics step
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct BodyGravity {
+    pub direction: GravityDirection,
+    pub strength: f32,
+}
+
+impl Default for BodyGravity {
+    fn default() -> Self {
+        BodyGravity {
+            direction: GravityDirection::default(),
+            strength: 1.0,
+        }
+    }
+}
+
+// how a body reacts to gravity. scale multiplies the pull, and bodies with a fixed
+// direction ignore gravity flips (but not gravity zones)
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct GravityResponse {
+    pub scale: f32,
+    pub fixed_direction: Option<GravityDirection>,
+}
+
+// how hard a body is to push, the wizard weighs 1. never below MIN_MASS, levels are
+// clamped on load
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct Mass(pub f32);
 
 #[derive(Component)]
 pub struct Velocity {
@@ -30,211 +134,943 @@ pub struct EntitySize {
     pub height: f32,
 }
 
-pub fn flip_gravity(keyboard_input: Res<Input<KeyCode>>, mut gravity: ResMut<Gravity>) {
-    let space = keyboard_input.just_pressed(KeyCode::Space);
-    if space {
-        match gravity.0 {
-            GravityDirection::Down => gravity.0 = GravityDirection::Up,
-            GravityDirection::Up => gravity.0 = GravityDirection::Down,
+// where a body was after the last two physics steps. the transform holds the physics
+// position while the physics systems run, and a blend of the two while rendering
+#[derive(Component)]
+pub struct PhysicsInterpolation {
+    pub previous: Vec2,
+    pub current: Vec2,
+}
+
+impl PhysicsInterpolation {
+    pub fn new(position: Vec2) -> Self {
+        PhysicsInterpolation {
+            previous: position,
+            current: position,
         }
     }
+
+    // the box a body covers where physics put it. gameplay checks overlap these, the
+    // drawn transform lags up to a step behind
+    pub fn aabb(&self, size: &EntitySize) -> Aabb {
+        Aabb::from_center(self.current, Vec2::new(size.width, size.height))
+    }
+}
+
+pub fn physics_dt(fixed_time: &FixedTime) -> f32 {
+    fixed_time.period.as_secs_f32()
+}
+
+// first physics system: put the bodies back where the physics left them
+pub fn restore_physics_position(mut query: Query<(&mut Transform, &mut PhysicsInterpolation)>) {
+    for (mut transform, mut interpolation) in query.iter_mut() {
+        interpolation.previous = interpolation.current;
+        transform.translation.x = interpolation.current.x;
+        transform.translation.y = interpolation.current.y;
+    }
+}
+
+// last physics system
+pub fn store_physics_position(mut query: Query<(&Transform, &mut PhysicsInterpolation)>) {
+    for (transform, mut interpolation) in query.iter_mut() {
+        interpolation.current = transform.translation.truncate();
+    }
+}
+
+// draw the bodies between the last two physics steps, by how far we are into the next one
+pub fn interpolate_transforms(
+    mut query: Query<(&mut Transform, &PhysicsInterpolation)>,
+    fixed_time: Res<FixedTime>,
+) {
+    let alpha = (fixed_time.accumulated().as_secs_f32() / physics_dt(&fixed_time)).min(1.0);
+    for (mut transform, interpolation) in query.iter_mut() {
+        let position = interpolation.previous.lerp(interpolation.current, alpha);
+        transform.translation.x = position.x;
+        transform.translation.y = position.y;
+    }
+}
+
+// space flips gravity around, i/j/k/l point it up/left/down/right
+pub fn flip_gravity(keyboard_input: Res<Input<KeyCode>>, mut gravity: ResMut<Gravity>) {
+    if keyboard_input.just_pressed(KeyCode::Space) {
+        gravity.0 = gravity.0.opposite();
+    } else if keyboard_input.just_pressed(KeyCode::I) {
+        gravity.0 = GravityDirection::Up;
+    } else if keyboard_input.just_pressed(KeyCode::J) {
+        gravity.0 = GravityDirection::Left;
+    } else if keyboard_input.just_pressed(KeyCode::K) {
+        gravity.0 = GravityDirection::Down;
+    } else if keyboard_input.just_pressed(KeyCode::L) {
+        gravity.0 = GravityDirection::Right;
+    }
+}
+
+// picks the gravity of every body. where gravity zones overlap the smallest one wins,
+// so zones can be nested
+pub fn body_gravity(
+    mut body_query: Query<(
+        &Transform,
+        &EntitySize,
+        &mut BodyGravity,
+        Option<&GravityResponse>,
+    )>,
+    zone_query: Query<&GravityZone>,
+    gravity: Res<Gravity>,
+) {
+    for (transform, size, mut body_gravity, response) in body_query.iter_mut() {
+        let center = transform.translation;
+        let zone = zone_query
+            .iter()
+            .filter(|zone| {
+                zone.active
+                    && zone.contains(
+                        center.x - size.width / 2.0,
+                        center.x + size.width / 2.0,
+                        center.y - size.height / 2.0,
+                        center.y + size.height / 2.0,
+                    )
+            })
+            .min_by(|a, b| a.area().total_cmp(&b.area()));
+        let (direction, strength) = match zone {
+            Some(zone) => (zone.direction, zone.strength),
+            None => (
+                response
+                    .and_then(|response| response.fixed_direction)
+                    .unwrap_or(gravity.0),
+                1.0,
+            ),
+        };
+        let scale = response.map_or(1.0, |response| response.scale);
+        *body_gravity = BodyGravity {
+            direction,
+            strength: strength * scale,
+        };
+    }
 }
 
-pub fn apply_movement(mut query: Query<(&mut Transform, &Velocity)>, time: Res<Time>) {
-    for (mut transform, velocity) in query.iter_mut() {
-        transform.translation.x += velocity.x * time.delta_seconds();
-        transform.translation.y += velocity.y * time.delta_seconds();
+// crates slide to a stop on their own, unless something pushes them from behind.
+// on the ground the floor's material scales how fast
+pub fn object_friction(
+    mut object_query: Query<(&mut Velocity, &BodyGravity, &Contacts), With<Object>>,
+    config: Res<PhysicsConfig>,
+    fixed_time: Res<FixedTime>,
+) {
+    let dt = physics_dt(&fixed_time);
+    for (mut velocity, gravity, contacts) in object_query.iter_mut() {
+        let material = if contacts.floor {
+            contacts.floor_material.friction()
+        } else {
+            1.0
+        };
+        let friction = config.object_friction * material * dt;
+        let speed = velocity_along(&mut velocity, gravity.direction.right());
+        if *speed > 0.0 && !contacts.left {
+            *speed = (*speed - friction).max(0.0);
+        } else if *speed < 0.0 && !contacts.right {
+            *speed = (*speed + friction).min(0.0);
+        }
     }
 }
+
 // apply gravity to objects with velocity
-pub fn gravitational_pull(mut velocity_query: Query<&mut Velocity>, gravity: Res<Gravity>) {
-    match gravity.0 {
-        GravityDirection::Down => {
-            for mut velocity in velocity_query.iter_mut() {
-                velocity.y -= GRAVITY;
-                if velocity.y < -MAX_VERTICAL_VELOCITY {
-                    velocity.y = -MAX_VERTICAL_VELOCITY;
-                }
-            }
+pub fn gravitational_pull(
+    mut velocity_query: Query<(&mut Velocity, &BodyGravity)>,
+    config: Res<PhysicsConfig>,
+    fixed_time: Res<FixedTime>,
+) {
+    let dt = physics_dt(&fixed_time);
+    for (mut velocity, gravity) in velocity_query.iter_mut() {
+        let pull = config.gravity * gravity.strength * dt;
+        let down = gravity.direction.down();
+        // whether gravity pulls towards positive coordinates
+        let sign = down.x + down.y;
+        let speed = velocity_along(&mut velocity, down);
+        // only falling is capped, bodies can still be thrown against gravity faster
+        *speed = if sign > 0.0 {
+            (*speed + pull).min(config.max_vertical_velocity)
+        } else {
+            (*speed - pull).max(-config.max_vertical_velocity)
+        };
+    }
+}
+// axis aligned bounding box, by its corners
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+impl Aabb {
+    pub fn from_center(center: Vec2, size: Vec2) -> Self {
+        Aabb {
+            min: center - size / 2.0,
+            max: center + size / 2.0,
         }
-        GravityDirection::Up => {
-            for mut velocity in velocity_query.iter_mut() {
-                velocity.y += GRAVITY;
-                if velocity.y > MAX_VERTICAL_VELOCITY {
-                    velocity.y = MAX_VERTICAL_VELOCITY;
+    }
+
+    pub fn center(&self) -> Vec2 {
+        (self.min + self.max) / 2.0
+    }
+
+    pub fn translated(&self, offset: Vec2) -> Self {
+        Aabb {
+            min: self.min + offset,
+            max: self.max + offset,
+        }
+    }
+
+    // the area covered while moving by delta
+    pub fn swept(&self, delta: Vec2) -> Self {
+        Aabb {
+            min: self.min.min(self.min + delta),
+            max: self.max.max(self.max + delta),
+        }
+    }
+
+    pub fn expanded(&self, margin: f32) -> Self {
+        Aabb {
+            min: self.min - margin,
+            max: self.max + margin,
+        }
+    }
+
+    // touching edges don't count
+    pub fn overlaps(&self, other: &Aabb) -> bool {
+        self.min.x < other.max.x
+            && self.max.x > other.min.x
+            && self.min.y < other.max.y
+            && self.max.y > other.min.y
+    }
+}
+
+impl From<&Platform> for Aabb {
+    fn from(platform: &Platform) -> Self {
+        Aabb {
+            min: Vec2::new(platform.lowx, platform.lowy),
+            max: Vec2::new(platform.highx, platform.highy),
+        }
+    }
+}
+
+// first contact of a swept box: fraction of the movement done before touching,
+// and the normal of the touched surface
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hit {
+    pub time: f32,
+    pub normal: Vec2,
+}
+
+// entry and exit time of a box moving by delta along one axis, relative to a target
+fn sweep_axis(
+    min: f32,
+    max: f32,
+    delta: f32,
+    target_min: f32,
+    target_max: f32,
+) -> Option<(f32, f32)> {
+    if delta > 0.0 {
+        Some(((target_min - max) / delta, (target_max - min) / delta))
+    } else if delta < 0.0 {
+        Some(((target_max - min) / delta, (target_min - max) / delta))
+    } else if max > target_min && min < target_max {
+        Some((f32::NEG_INFINITY, f32::INFINITY))
+    } else {
+        None
+    }
+}
+
+// swept aabb test: where, along delta, does the moving box first touch the target.
+// boxes that already overlap are ignored so they can separate, unless they are moving
+// further into each other and only overlap by rounding errors: that's a hit right away
+pub fn sweep(moving: &Aabb, delta: Vec2, target: &Aabb) -> Option<Hit> {
+    let (entry_x, exit_x) = sweep_axis(
+        moving.min.x,
+        moving.max.x,
+        delta.x,
+        target.min.x,
+        target.max.x,
+    )?;
+    let (entry_y, exit_y) = sweep_axis(
+        moving.min.y,
+        moving.max.y,
+        delta.y,
+        target.min.y,
+        target.max.y,
+    )?;
+
+    let mut entry = entry_x.max(entry_y);
+    let exit = exit_x.min(exit_y);
+    let depth = if entry_x > entry_y {
+        -entry_x * delta.x.abs()
+    } else {
+        -entry_y * delta.y.abs()
+    };
+    if entry < 0.0 && depth <= CONTACT_DISTANCE {
+        entry = 0.0;
+    }
+    if entry > exit || !(0.0..=1.0).contains(&entry) || exit <= 0.0 {
+        return None;
+    }
+    // landing on a corner counts as landing on top
+    let normal = if entry_x > entry_y {
+        Vec2::new(-delta.x.signum(), 0.0)
+    } else {
+        Vec2::new(0.0, -delta.y.signum())
+    };
+    Some(Hit {
+        time: entry,
+        normal,
+    })
+}
+
+// something a body can bump into: where it is at the start of the step and how it moves.
+// bodies touching it take on its surface velocity along the normal. that's its velocity,
+// except for bodies that already moved this step: those sit still where they ended up,
+// but still carry along whatever runs into them.
+// kinematic obstacles are platforms: when they move they push bodies out of their way
+#[derive(Debug, Clone, Copy)]
+pub struct Obstacle {
+    pub aabb: Aabb,
+    pub velocity: Vec2,
+    pub surface_velocity: Vec2,
+    pub material: PlatformMaterial,
+    pub kinematic: bool,
+}
+
+impl Obstacle {
+    pub fn fixed(aabb: Aabb) -> Self {
+        Obstacle {
+            aabb,
+            velocity: Vec2::ZERO,
+            surface_velocity: Vec2::ZERO,
+            material: PlatformMaterial::Normal,
+            kinematic: false,
+        }
+    }
+}
+
+impl From<&Platform> for Obstacle {
+    fn from(platform: &Platform) -> Self {
+        Obstacle {
+            material: platform.material,
+            kinematic: true,
+            ..Obstacle::fixed(Aabb::from(platform))
+        }
+    }
+}
+
+// moves a box by velocity * dt. on contact it is snapped flush to the surface, its velocity
+// along the normal becomes the obstacle's surface velocity, or bounces off it, and the rest
+// of the movement slides along it.
+// returns the new box, the new velocity and the normals of everything it touched
+pub fn move_and_slide(
+    mut aabb: Aabb,
+    mut velocity: Vec2,
+    dt: f32,
+    obstacles: &[Obstacle],
+) -> (Aabb, Vec2, Vec<Vec2>) {
+    let mut normals = Vec::new();
+    // fraction of the step already done
+    let mut done = 0.0;
+    // one slide per axis, the last pass can only move freely
+    for _ in 0..3 {
+        let remaining = 1.0 - done;
+        if remaining <= 0.0 {
+            break;
+        }
+        let mut first: Option<(Hit, &Obstacle)> = None;
+        for obstacle in obstacles.iter() {
+            // obstacles move during the step too, so sweep with the relative movement
+            let target = obstacle.aabb.translated(obstacle.velocity * dt * done);
+            let delta = (velocity - obstacle.velocity) * dt * remaining;
+            if let Some(hit) = sweep(&aabb, delta, &target) {
+                // something catching up from behind doesn't stop us, it bumps into us
+                // when it moves itself. platforms don't, so they push
+                let approach = if obstacle.kinematic {
+                    velocity - obstacle.velocity
+                } else {
+                    velocity
+                };
+                if approach.dot(hit.normal) >= 0.0 {
+                    continue;
+                }
+                if first.map_or(true, |(closest, _)| hit.time < closest.time) {
+                    first = Some((hit, obstacle));
                 }
             }
         }
+
+        let Some((hit, obstacle)) = first else {
+            aabb = aabb.translated(velocity * dt * remaining);
+            break;
+        };
+        let step = hit.time * remaining;
+        aabb = aabb.translated(velocity * dt * step);
+        done += step;
+
+        // snap flush to the contact surface to get rid of rounding errors
+        let target = obstacle.aabb.translated(obstacle.velocity * dt * done);
+        let size = aabb.max - aabb.min;
+        if hit.normal.x > 0.0 {
+            aabb.min.x = target.max.x;
+            aabb.max.x = target.max.x + size.x;
+        } else if hit.normal.x < 0.0 {
+            aabb.max.x = target.min.x;
+            aabb.min.x = target.min.x - size.x;
+        } else if hit.normal.y > 0.0 {
+            aabb.min.y = target.max.y;
+            aabb.max.y = target.max.y + size.y;
+        } else {
+            aabb.max.y = target.min.y;
+            aabb.min.y = target.min.y - size.y;
+        }
+
+        let surface = obstacle.surface_velocity;
+        let impact = -(velocity - surface).dot(hit.normal);
+        let bounce = if impact > MIN_BOUNCE_SPEED {
+            impact * obstacle.material.restitution()
+        } else {
+            0.0
+        };
+        if hit.normal.x != 0.0 {
+            velocity.x = surface.x + hit.normal.x * bounce;
+        } else {
+            velocity.y = surface.y + hit.normal.y * bounce;
+        }
+        normals.push(hit.normal);
     }
+    (aabb, velocity, normals)
 }
-// handle collision between objects with velocity and platforms
-pub fn platform_collision(
-    mut velocity_query: Query<(&mut Velocity, &Transform, &EntitySize)>,
-    platform_query: Query<&Platform>,
-    time: Res<Time>,
-) {
-    for (mut velocity, transform, size) in velocity_query.iter_mut() {
-        for platform in platform_query.iter() {
-            let highx = transform.translation.x + size.width / 2.0;
-            let lowx = transform.translation.x - size.width / 2.0;
-            let highy = transform.translation.y + size.height / 2.0;
-            let lowy = transform.translation.y - size.height / 2.0;
-
-            let above = lowy >= platform.highy;
-            let below = highy <= platform.lowy;
-            let left = highx <= platform.lowx;
-            let right = lowx >= platform.highx;
-
-            // collision from above
-            if above && !right && !left && lowy + velocity.y * time.delta_seconds() < platform.highy
-            {
-                velocity.y = 0.0;
-            } else if below
-                && !right
-                && !left
-                && highy + velocity.y * time.delta_seconds() > platform.lowy
-            {
-                velocity.y = 0.0;
-            }
 
-            // collision from the sides
-            if left && !above && !below && highx + velocity.x * time.delta_seconds() > platform.lowx
-            {
-                velocity.x = 0.0;
-            } else if right
-                && !above
-                && !below
-                && lowx + velocity.x * time.delta_seconds() < platform.highx
-            {
-                velocity.x = 0.0;
-            }
+// how close two boxes have to be to count as touching
+pub const CONTACT_DISTANCE: f32 = 0.01;
+
+// the sides of a body that touch something, relative to gravity: the floor is what it
+// stands on, left and right are where it walks. filled in by apply_movement every step
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Contacts {
+    pub floor: bool,
+    pub ceiling: bool,
+    pub left: bool,
+    pub right: bool,
+    // what the floor is made of, the first one found when standing on several
+    pub floor_material: PlatformMaterial,
+}
 
-            // rare case: collision from the corners
-            if (above && left)
-                && lowy + velocity.y * time.delta_seconds() < platform.highy
-                && highx + velocity.x * time.delta_seconds() > platform.lowx
-            {
-                velocity.y = 0.0;
-                velocity.x = 0.0;
-            } else if (above && right)
-                && lowy + velocity.y * time.delta_seconds() < platform.highy
-                && lowx + velocity.x * time.delta_seconds() < platform.highx
-            {
-                velocity.y = 0.0;
-                velocity.x = 0.0;
-            } else if (below && left)
-                && highy + velocity.y * time.delta_seconds() > platform.lowy
-                && highx + velocity.x * time.delta_seconds() > platform.lowx
-            {
-                velocity.y = 0.0;
-                velocity.x = 0.0;
-            } else if (below && right)
-                && highy + velocity.y * time.delta_seconds() > platform.lowy
-                && lowx + velocity.x * time.delta_seconds() < platform.highx
-            {
-                velocity.y = 0.0;
-                velocity.x = 0.0;
+impl Contacts {
+    // marks the side facing the world direction `side`
+    fn add(&mut self, side: Vec2, gravity: GravityDirection, material: PlatformMaterial) {
+        if side == gravity.down() {
+            if !self.floor {
+                self.floor_material = material;
             }
+            self.floor = true;
+        } else if side == -gravity.down() {
+            self.ceiling = true;
+        } else if side == gravity.right() {
+            self.right = true;
+        } else if side == -gravity.right() {
+            self.left = true;
+        }
+    }
+
+    // whether the side facing the world direction `side` touches something
+    pub fn touches(&self, side: Vec2, gravity: GravityDirection) -> bool {
+        if side == gravity.down() {
+            self.floor
+        } else if side == -gravity.down() {
+            self.ceiling
+        } else if side == gravity.right() {
+            self.right
+        } else if side == -gravity.right() {
+            self.left
+        } else {
+            false
         }
     }
 }
 
-pub fn object_collision(
-    mut velocity_query: Query<(&mut Velocity, &Transform, &EntitySize)>,
-    time: Res<Time>,
-) {
-    for (mut velocity, transform, size) in velocity_query.iter() {
-        for (object_velocity, object_transform, object_entitysize) in velocity_query.iter() {
-            // skip if the object is the same
-            if transform.translation == object_transform.translation {
-                continue;
+// world direction from a box to another box lying flush against it, if they touch.
+// boxes that only meet at a corner don't touch
+pub fn touching_side(aabb: &Aabb, other: &Aabb) -> Option<Vec2> {
+    let overlap_x = aabb.max.x > other.min.x && aabb.min.x < other.max.x;
+    let overlap_y = aabb.max.y > other.min.y && aabb.min.y < other.max.y;
+    if overlap_x && (aabb.min.y - other.max.y).abs() <= CONTACT_DISTANCE {
+        Some(Vec2::NEG_Y)
+    } else if overlap_x && (other.min.y - aabb.max.y).abs() <= CONTACT_DISTANCE {
+        Some(Vec2::Y)
+    } else if overlap_y && (aabb.min.x - other.max.x).abs() <= CONTACT_DISTANCE {
+        Some(Vec2::NEG_X)
+    } else if overlap_y && (other.min.x - aabb.max.x).abs() <= CONTACT_DISTANCE {
+        Some(Vec2::X)
+    } else {
+        None
+    }
+}
+
+// a body taking part in apply_movement
+#[derive(Clone, Copy)]
+struct Body {
+    entity: Entity,
+    start: Aabb,
+    end: Aabb,
+    velocity: Vec2,
+    moved: bool,
+    object: bool,
+    mass: f32,
+    gravity: GravityDirection,
+    // the velocity of the moving platform it stands on, part of velocity during the step
+    carried: Vec2,
+}
+
+impl Body {
+    // bodies that already moved stay put where they ended up for the others
+    fn obstacle(&self) -> Obstacle {
+        if self.moved {
+            Obstacle {
+                aabb: self.end,
+                velocity: Vec2::ZERO,
+                surface_velocity: self.velocity,
+                material: PlatformMaterial::Normal,
+                kinematic: false,
             }
-            let highx = transform.translation.x + size.width / 2.0;
-            let lowx = transform.translation.x - size.width / 2.0;
-            let highy = transform.translation.y + size.height / 2.0;
-            let lowy = transform.translation.y - size.height / 2.0;
-
-            let object_highx = object_transform.translation.x + object_entitysize.width / 2.0;
-            let object_lowx = object_transform.translation.x - object_entitysize.width / 2.0;
-            let object_highy = object_transform.translation.y + object_entitysize.height / 2.0;
-            let object_lowy = object_transform.translation.y - object_entitysize.height / 2.0;
-
-            let above = lowy >= object_highy;
-            let below = highy <= object_lowy;
-            let left = highx <= object_lowx;
-            let right = lowx >= object_highx;
-
-            // difference: 2 objects can be moving at the same time and collide
-            // collision from above
-            if above
-                && !right
-                && !left
-                && lowy + velocity.y * time.delta_seconds()
-                    < object_highy + object_velocity.y * time.delta_seconds()
-            {
-                velocity.y = 0.0;
-            } else if below
-                && !right
-                && !left
-                && highy + velocity.y * time.delta_seconds()
-                    > object_lowy + object_velocity.y * time.delta_seconds()
-            {
-                velocity.y = 0.0;
+        } else {
+            Obstacle {
+                aabb: self.start,
+                velocity: self.velocity,
+                surface_velocity: self.velocity,
+                material: PlatformMaterial::Normal,
+                kinematic: false,
             }
+        }
+    }
+}
 
-            // collision from the sides
-            if left
-                && !above
-                && !below
-                && highx + velocity.x * time.delta_seconds()
-                    > object_lowx + object_velocity.x * time.delta_seconds()
-            {
-                velocity.x = 0.0;
-            } else if right
-                && !above
-                && !below
-                && lowx + velocity.x * time.delta_seconds()
-                    < object_highx + object_velocity.x * time.delta_seconds()
-            {
-                velocity.x = 0.0;
+// crates that ran into each other this step share their momentum along the contact normal.
+// the collision is perfectly inelastic, so they move on together or rest on each other.
+// this uses the velocities from before the move, bumping into the other crate stopped
+// the faster one. a few passes so a push carries through a row of crates
+fn exchange_momentum(bodies: &mut [Body], before: &[Vec2], body_grid: &SpatialGrid) {
+    let mut velocities = before.to_vec();
+    let mut exchanged = vec![BVec2::FALSE; bodies.len()];
+    let mut found = Vec::new();
+    for _ in 0..4 {
+        for i in 0..bodies.len() {
+            body_grid.query(&bodies[i].end.expanded(CONTACT_DISTANCE), &mut found);
+            for &j in found.iter().filter(|j| **j > i && **j < bodies.len()) {
+                let (a, b) = (&bodies[i], &bodies[j]);
+                if !a.object || !b.object {
+                    continue;
+                }
+                let Some(side) = touching_side(&a.end, &b.end) else {
+                    continue;
+                };
+                let speed_a = velocities[i].dot(side);
+                let speed_b = velocities[j].dot(side);
+                // already moving apart or together
+                if speed_a <= speed_b {
+                    continue;
+                }
+                let shared = (a.mass * speed_a + b.mass * speed_b) / (a.mass + b.mass);
+                velocities[i] += side * (shared - speed_a);
+                velocities[j] += side * (shared - speed_b);
+                let axis = BVec2::new(side.x != 0.0, side.y != 0.0);
+                exchanged[i] |= axis;
+                exchanged[j] |= axis;
             }
+        }
+    }
+    for ((body, velocity), exchanged) in bodies.iter_mut().zip(velocities).zip(exchanged) {
+        body.velocity = Vec2::select(exchanged, velocity, body.velocity);
+    }
+}
 
-            // rare case: collision from the corners
-            if (above && left)
-                && lowy + velocity.y * time.delta_seconds()
-                    < object_highy + object_velocity.y * time.delta_seconds()
-                && highx + velocity.x * time.delta_seconds()
-                    > object_lowx + object_velocity.x * time.delta_seconds()
-            {
-                velocity.y = 0.0;
-                velocity.x = 0.0;
-            } else if (above && right)
-                && lowy + velocity.y * time.delta_seconds()
-                    < object_highy + object_velocity.y * time.delta_seconds()
-                && lowx + velocity.x * time.delta_seconds()
-                    < object_highx + object_velocity.x * time.delta_seconds()
-            {
-                velocity.y = 0.0;
-                velocity.x = 0.0;
-            } else if (below && left)
-                && highy + velocity.y * time.delta_seconds()
-                    > object_lowy + object_velocity.y * time.delta_seconds()
-                && highx + velocity.x * time.delta_seconds()
-                    > object_lowx + object_velocity.x * time.delta_seconds()
-            {
-                velocity.y = 0.0;
-                velocity.x = 0.0;
-            } else if (below && right)
-                && highy + velocity.y * time.delta_seconds()
-                    > object_lowy + object_velocity.y * time.delta_seconds()
-                && lowx + velocity.x * time.delta_seconds()
-                    < object_highx + object_velocity.x * time.delta_seconds()
-            {
-                velocity.y = 0.0;
-                velocity.x = 0.0;
+// move everything with a velocity, stopping flush against platforms and other bodies.
+// crates go first, in the direction of their gravity, so stacks settle from the bottom up
+// and the wizard always bumps into where they ended up. crates that collided exchange
+// momentum by their mass before the wizard moves.
+// bodies on moving platforms are carried along on top of their own velocity.
+// afterwards every body's Contacts are updated from where everything ended up.
+// only what the grids find near a body is looked at
+#[allow(clippy::type_complexity)]
+pub fn apply_movement(
+    mut body_query: Query<(
+        Entity,
+        &mut Transform,
+        &mut Velocity,
+        &EntitySize,
+        Option<&Object>,
+        Option<&mut Contacts>,
+        Option<&BodyGravity>,
+        Option<&Mass>,
+    )>,
+    platform_grid: Res<PlatformGrid>,
+    gravity: Res<Gravity>,
+    fixed_time: Res<FixedTime>,
+) {
+    let dt = physics_dt(&fixed_time);
+
+    let mut bodies: Vec<Body> = body_query
+        .iter()
+        .map(
+            |(entity, transform, velocity, size, object, _, body_gravity, mass)| {
+                let aabb = Aabb::from_center(
+                    transform.translation.truncate(),
+                    Vec2::new(size.width, size.height),
+                );
+                Body {
+                    entity,
+                    start: aabb,
+                    end: aabb,
+                    velocity: Vec2::new(velocity.x, velocity.y),
+                    moved: false,
+                    object: object.is_some(),
+                    mass: mass.map_or(1.0, |mass| mass.0),
+                    gravity: body_gravity.map_or(gravity.0, |body_gravity| body_gravity.direction),
+                    carried: Vec2::ZERO,
+                }
+            },
+        )
+        .collect();
+    // lowest first, each seen from its own gravity, so stacks in a gravity zone settle
+    // from their own bottom up
+    let height = |body: &Body| -body.start.center().dot(body.gravity.down());
+    bodies.sort_by(|a, b| {
+        b.object
+            .cmp(&a.object)
+            .then_with(|| height(a).total_cmp(&height(b)))
+    });
+
+    // bodies standing on a moving platform ride along with it, and pass that on to
+    // whatever stands on them. lowest first, so the ones below are already known
+    let mut found = Vec::new();
+    let start_grid = SpatialGrid::build(bodies.iter().map(|body| &body.start));
+    for i in 0..bodies.len() {
+        let body = bodies[i];
+        let nearby = body.start.expanded(CONTACT_DISTANCE);
+        let stands_on =
+            |other: &Aabb| touching_side(&body.start, other) == Some(body.gravity.down());
+        let mut carried = platform_grid
+            .query(&nearby, &mut found)
+            .find(|platform| platform.velocity != Vec2::ZERO && stands_on(&platform.aabb))
+            .map(|platform| platform.velocity);
+        if carried.is_none() {
+            start_grid.query(&nearby, &mut found);
+            carried = found
+                .iter()
+                .map(|j| &bodies[*j])
+                .find(|other| other.carried != Vec2::ZERO && stands_on(&other.start))
+                .map(|other| other.carried);
+        }
+        if let Some(carried) = carried {
+            bodies[i].carried = carried;
+            bodies[i].velocity += carried;
+        }
+    }
+
+    let before: Vec<Vec2> = bodies.iter().map(|body| body.velocity).collect();
+    // every body is in the grid with the area it would sweep, and where it ended up once
+    // it has moved
+    let swept: Vec<Aabb> = bodies
+        .iter()
+        .map(|body| body.start.swept(body.velocity * dt))
+        .collect();
+    let mut body_grid = SpatialGrid::build(swept.iter());
+
+    let mut obstacles = Vec::new();
+    let mut move_body = |bodies: &mut [Body], body_grid: &mut SpatialGrid, i: usize| {
+        obstacles.clear();
+        obstacles.extend(platform_grid.query(&swept[i], &mut found).copied());
+        body_grid.query(&swept[i], &mut found);
+        obstacles.extend(
+            found
+                .iter()
+                .filter(|j| **j != i)
+                .map(|j| bodies[*j].obstacle()),
+        );
+        let body = &mut bodies[i];
+        let (aabb, velocity, _) = move_and_slide(body.start, body.velocity, dt, &obstacles);
+        body.end = aabb;
+        body.velocity = velocity;
+        body.moved = true;
+        body_grid.insert(i, &aabb);
+    };
+    // the wizard only moves once the crates have settled their collisions
+    let objects = bodies.iter().take_while(|body| body.object).count();
+    for i in 0..objects {
+        move_body(&mut bodies, &mut body_grid, i);
+    }
+    exchange_momentum(&mut bodies[..objects], &before[..objects], &body_grid);
+    for i in objects..bodies.len() {
+        move_body(&mut bodies, &mut body_grid, i);
+    }
+
+    for (i, body) in bodies.iter().enumerate() {
+        let Ok((_, mut transform, mut velocity, _, _, contacts, _, _)) =
+            body_query.get_mut(body.entity)
+        else {
+            continue;
+        };
+        let center = body.end.center();
+        transform.translation.x = center.x;
+        transform.translation.y = center.y;
+
+        // platforms are checked where they ended up
+        let mut touching = Contacts::default();
+        let nearby = body.end.expanded(CONTACT_DISTANCE);
+        let platforms: Vec<(Aabb, PlatformMaterial, Vec2)> = platform_grid
+            .query(&nearby, &mut found)
+            .map(|platform| {
+                (
+                    platform.aabb.translated(platform.velocity * dt),
+                    platform.material,
+                    platform.velocity,
+                )
+            })
+            .collect();
+        body_grid.query(&nearby, &mut found);
+        let others = found
+            .iter()
+            .filter(|j| **j != i)
+            .map(|j| (bodies[*j].end, PlatformMaterial::Normal, bodies[*j].carried));
+        // what the floor it ended up on carries, that part of the velocity isn't its own.
+        // bodies that got off keep it
+        let mut floor_carries = None;
+        for (other, material, carries) in platforms.into_iter().chain(others) {
+            if let Some(side) = touching_side(&body.end, &other) {
+                touching.add(side, body.gravity, material);
+                if side == body.gravity.down() && floor_carries.is_none() {
+                    floor_carries = Some(carries);
+                }
             }
         }
+        let own = body.velocity - floor_carries.unwrap_or(Vec2::ZERO);
+        velocity.x = own.x;
+        velocity.y = own.y;
+        if let Some(mut contacts) = contacts {
+            *contacts = touching;
+        }
     }
 }
 
-// todo: handle collision between objects with velocity and other objects with velocity
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::broadphase::update_platform_grid;
+    use crate::level::PathMode;
+    use crate::moving_platform::{move_platforms, PlatformPath};
+
+    const CRATE: f32 = 20.0;
+
+    fn world(gravity: GravityDirection) -> World {
+        let mut world = World::new();
+        world.insert_resource(Gravity(gravity));
+        world.insert_resource(PhysicsConfig::default());
+        world.insert_resource(FixedTime::new_from_secs(PHYSICS_TIMESTEP));
+        world.insert_resource(PlatformGrid::default());
+        world
+    }
+
+    fn spawn_platform(world: &mut World, lowx: f32, highx: f32, lowy: f32, highy: f32) {
+        world.spawn(Platform {
+            lowx,
+            highx,
+            lowy,
+            highy,
+            material: PlatformMaterial::Normal,
+        });
+    }
+
+    fn spawn_crate(world: &mut World, center: Vec2, velocity: Vec2, mass: f32) -> Entity {
+        world
+            .spawn((
+                Object,
+                Transform::from_xyz(center.x, center.y, 0.0),
+                Velocity {
+                    x: velocity.x,
+                    y: velocity.y,
+                },
+                EntitySize {
+                    width: CRATE,
+                    height: CRATE,
+                },
+                Contacts::default(),
+                BodyGravity::default(),
+                Mass(mass),
+            ))
+            .id()
+    }
+
+    fn run_steps(world: &mut World, steps: usize) {
+        let mut schedule = Schedule::new();
+        schedule.add_systems(
+            (
+                body_gravity,
+                gravitational_pull,
+                update_platform_grid,
+                apply_movement,
+                move_platforms,
+            )
+                .chain(),
+        );
+        for _ in 0..steps {
+            schedule.run(world);
+        }
+    }
+
+    fn aabb(world: &World, entity: Entity) -> Aabb {
+        let center = world
+            .get::<Transform>(entity)
+            .unwrap()
+            .translation
+            .truncate();
+        Aabb::from_center(center, Vec2::splat(CRATE))
+    }
+
+    fn velocity(world: &World, entity: Entity) -> Vec2 {
+        let velocity = world.get::<Velocity>(entity).unwrap();
+        Vec2::new(velocity.x, velocity.y)
+    }
+
+    #[test]
+    fn stacked_crates_fall_together_when_gravity_flips() {
+        let mut world = world(GravityDirection::Down);
+        // a floor at 100 and a ceiling at 300
+        spawn_platform(&mut world, 0.0, 400.0, 0.0, 100.0);
+        spawn_platform(&mut world, 0.0, 400.0, 300.0, 400.0);
+        // spawned top first, so they can't rely on coming out of the query lowest first
+        let top = spawn_crate(&mut world, Vec2::new(200.0, 130.0), Vec2::ZERO, 1.0);
+        let bottom = spawn_crate(&mut world, Vec2::new(200.0, 110.0), Vec2::ZERO, 1.0);
+
+        run_steps(&mut world, 10);
+        assert_eq!(aabb(&world, bottom).min.y, 100.0);
+        assert_eq!(aabb(&world, top).min.y, 120.0);
+        assert_eq!(velocity(&world, top), Vec2::ZERO);
+        assert_eq!(velocity(&world, bottom), Vec2::ZERO);
+        assert!(world.get::<Contacts>(top).unwrap().floor);
+
+        // the top crate is now the lowest one and lands first, the other one on it
+        world.resource_mut::<Gravity>().0 = GravityDirection::Up;
+        run_steps(&mut world, 60);
+        assert_eq!(aabb(&world, top).max.y, 300.0);
+        assert_eq!(aabb(&world, bottom).max.y, 280.0);
+        assert_eq!(velocity(&world, top), Vec2::ZERO);
+        assert_eq!(velocity(&world, bottom), Vec2::ZERO);
+        assert_eq!(
+            touching_side(&aabb(&world, bottom), &aabb(&world, top)),
+            Some(Vec2::Y)
+        );
+        let contacts = world.get::<Contacts>(bottom).unwrap();
+        assert!(contacts.floor && !contacts.ceiling);
+        assert!(world.get::<Contacts>(top).unwrap().floor);
+    }
+
+    #[test]
+    fn a_crate_rides_a_platform_moving_up_and_down() {
+        let mut world = world(GravityDirection::Down);
+        // 100 wide and 20 high, going between 100 and 300 at the default speed
+        let points = vec![Vec2::new(200.0, 100.0), Vec2::new(200.0, 300.0)];
+        let platform = world
+            .spawn((
+                Platform {
+                    lowx: 150.0,
+                    highx: 250.0,
+                    lowy: 90.0,
+                    highy: 110.0,
+                    material: PlatformMaterial::Normal,
+                },
+                PlatformPath::new(points, PathMode::PingPong, 100.0),
+                Transform::from_xyz(200.0, 100.0, 0.0),
+            ))
+            .id();
+        let body = spawn_crate(
+            &mut world,
+            Vec2::new(200.0, 110.0 + CRATE / 2.0),
+            Vec2::ZERO,
+            1.0,
+        );
+
+        // a step moves it by a fraction of a pixel, the crate must never sink in and fall
+        // through. long enough to go up and down a few times
+        for step in 0..500 {
+            run_steps(&mut world, 1);
+            let top = world.get::<Platform>(platform).unwrap().highy;
+            let bottom = aabb(&world, body).min.y;
+            assert!(
+                (bottom - top).abs() <= CONTACT_DISTANCE,
+                "step {}: crate at {}, platform at {}",
+                step,
+                bottom,
+                top
+            );
+        }
+        assert!(world.get::<Contacts>(body).unwrap().floor);
+    }
+
+    #[test]
+    fn a_pushed_row_shares_momentum_by_mass() {
+        let mut world = world(GravityDirection::Down);
+        spawn_platform(&mut world, 0.0, 1000.0, 0.0, 100.0);
+        let y = 100.0 + CRATE / 2.0;
+        let row = [
+            spawn_crate(&mut world, Vec2::new(100.0, y), Vec2::new(300.0, 0.0), 1.0),
+            spawn_crate(&mut world, Vec2::new(120.0, y), Vec2::ZERO, 2.0),
+            spawn_crate(&mut world, Vec2::new(140.0, y), Vec2::ZERO, 1.0),
+        ];
+
+        // no friction here, so the row keeps the momentum of the first crate
+        run_steps(&mut world, 20);
+        let momentum: f32 = row
+            .iter()
+            .zip([1.0, 2.0, 1.0])
+            .map(|(entity, mass)| velocity(&world, *entity).x * mass)
+            .sum();
+        assert!((momentum - 300.0).abs() < 0.01, "momentum {}", momentum);
+        for entity in row {
+            let velocity = velocity(&world, entity);
+            assert!((velocity.x - 75.0).abs() < 0.5, "velocity {}", velocity);
+            assert_eq!(velocity.y, 0.0);
+            assert_eq!(aabb(&world, entity).min.y, 100.0);
+        }
+        // moved on together, nothing pushed into or away from its neighbour
+        for pair in row.windows(2) {
+            assert_eq!(
+                touching_side(&aabb(&world, pair[0]), &aabb(&world, pair[1])),
+                Some(Vec2::X)
+            );
+        }
+        assert!(aabb(&world, row[0]).min.x > 100.0);
+    }
+
+    #[test]
+    fn exchange_momentum_splits_by_mass() {
+        let body = |x: f32, mass: f32| {
+            let aabb = Aabb::from_center(Vec2::new(x, 0.0), Vec2::splat(CRATE));
+            Body {
+                entity: Entity::from_raw(0),
+                start: aabb,
+                end: aabb,
+                velocity: Vec2::ZERO,
+                moved: true,
+                object: true,
+                mass,
+                gravity: GravityDirection::Down,
+                carried: Vec2::ZERO,
+            }
+        };
+        // a light crate ran into a heavy one and was stopped by it
+        let mut bodies = [body(0.0, 1.0), body(20.0, 3.0)];
+        let before = [Vec2::new(200.0, 0.0), Vec2::ZERO];
+        let grid = SpatialGrid::build(bodies.iter().map(|body| &body.end));
+        exchange_momentum(&mut bodies, &before, &grid);
+        assert_eq!(bodies[0].velocity, Vec2::new(50.0, 0.0));
+        assert_eq!(bodies[1].velocity, Vec2::new(50.0, 0.0));
+
+        // crates that weren't moving towards each other are left alone
+        let mut bodies = [body(0.0, 1.0), body(20.0, 3.0)];
+        let before = [Vec2::new(-200.0, 0.0), Vec2::ZERO];
+        exchange_momentum(&mut bodies, &before, &grid);
+        assert_eq!(bodies[0].velocity, Vec2::ZERO);
+        assert_eq!(bodies[1].velocity, Vec2::ZERO);
+    }
+}
@@ -2,109 +2,291 @@
 //
 // wizard.rs
 //
+use crate::config::PhysicsConfig;
+use crate::enemy::Enemy;
 use crate::layout::*;
+use crate::level::{KeyColor, SpawnData};
 use crate::physics::*;
-use bevy::{prelude::*, window::PrimaryWindow};
+use bevy::prelude::*;
 
-pub const WIZARD_SIZE: f32 = 20.0;
 pub const WIZARD_COLOR_ACTIVE: Color = Color::rgb(0.0, 0.0, 1.0);
 pub const WIZARD_COLOR_PASSIVE: Color = Color::rgb(1.0, 0.0, 0.0);
-pub const WIZARD_SHAPE: Vec2 = Vec2::new(WIZARD_SIZE, WIZARD_SIZE);
 
 #[derive(Component)]
 pub struct Wizard;
 
+// keys the wizard carries, in the order they were picked up
+#[derive(Component, Default)]
+pub struct Inventory {
+    pub keys: Vec<KeyColor>,
+}
+
+// set by the level loader, used on first spawn and on every respawn
+#[derive(Resource, Default)]
+pub struct SpawnPoint {
+    pub spawn: SpawnData,
+    pub gravity: GravityDirection,
+}
+
+// walks along the floor, whichever way gravity points: left/right on floors and
+// ceilings, up/down on walls. how fast depends on being on the ground or in the air,
+// and on what the ground is made of
 pub fn wizard_input(
     keyboard_input: Res<Input<KeyCode>>,
-    mut wizard_velocity_query: Query<&mut Velocity, With<Wizard>>,
+    mut wizard_velocity_query: Query<(&mut Velocity, &BodyGravity, &Contacts), With<Wizard>>,
+    config: Res<PhysicsConfig>,
+    fixed_time: Res<FixedTime>,
 ) {
-    let mut velocity = wizard_velocity_query.single_mut();
+    let Ok((mut velocity, gravity, contacts)) = wizard_velocity_query.get_single_mut() else {
+        return;
+    };
+    let dt = physics_dt(&fixed_time);
+    let (acceleration, friction, max_speed) = if contacts.floor {
+        let material = contacts.floor_material;
+        (
+            config.ground_acceleration * material.acceleration() * dt,
+            config.ground_friction * material.friction() * dt,
+            config.max_horizontal_velocity * material.max_speed(),
+        )
+    } else {
+        (
+            config.air_acceleration * dt,
+            config.air_friction * dt,
+            config.max_horizontal_velocity,
+        )
+    };
     let mut direction = None;
 
-    let left = keyboard_input.pressed(KeyCode::Left) || keyboard_input.pressed(KeyCode::A);
-    let right = keyboard_input.pressed(KeyCode::Right) || keyboard_input.pressed(KeyCode::D);
+    let axis = gravity.direction.right();
+    let (left, right) = if axis.x != 0.0 {
+        (
+            keyboard_input.pressed(KeyCode::Left) || keyboard_input.pressed(KeyCode::A),
+            keyboard_input.pressed(KeyCode::Right) || keyboard_input.pressed(KeyCode::D),
+        )
+    } else {
+        (
+            keyboard_input.pressed(KeyCode::Down) || keyboard_input.pressed(KeyCode::S),
+            keyboard_input.pressed(KeyCode::Up) || keyboard_input.pressed(KeyCode::W),
+        )
+    };
 
     if left && !right {
-        direction = Some(-HORIZONTAL_ACCELERATION);
+        direction = Some(-acceleration);
     } else if right && !left {
-        direction = Some(HORIZONTAL_ACCELERATION);
+        direction = Some(acceleration);
     }
 
+    let speed = velocity_along(&mut velocity, axis);
     if let Some(value) = direction {
-        if (velocity.x > 0.0 && value < 0.0) || (velocity.x < 0.0 && value > 0.0) {
-            velocity.x += value * 2.0;
+        if (*speed > 0.0 && value < 0.0) || (*speed < 0.0 && value > 0.0) {
+            *speed += value * 2.0;
         } else {
-            velocity.x += value;
+            *speed += value;
         }
-    } else if velocity.x > 0.0 {
-        velocity.x -= HORIZONTAL_ACCELERATION;
-    } else if velocity.x < 0.0 {
-        velocity.x += HORIZONTAL_ACCELERATION;
+    } else if *speed > 0.0 {
+        *speed = (*speed - friction).max(0.0);
+    } else if *speed < 0.0 {
+        *speed = (*speed + friction).min(0.0);
     }
 
-    if velocity.x > MAX_HORIZONTAL_VELOCITY {
-        velocity.x = MAX_HORIZONTAL_VELOCITY;
-    } else if velocity.x < -MAX_HORIZONTAL_VELOCITY {
-        velocity.x = -MAX_HORIZONTAL_VELOCITY;
+    *speed = speed.clamp(-max_speed, max_speed);
+}
+
+// walking into the side of a crate speeds it up to the wizard's speed. heavier crates
+// only take their share of it: the wizard has to keep pushing to get them going
+#[allow(clippy::type_complexity)]
+pub fn push_objects(
+    wizard_query: Query<(&Velocity, &Transform, &EntitySize, &BodyGravity), With<Wizard>>,
+    mut object_query: Query<
+        (&mut Velocity, &Transform, &EntitySize, Option<&Mass>),
+        (With<Object>, Without<Wizard>),
+    >,
+    fixed_time: Res<FixedTime>,
+) {
+    let Ok((wizard_velocity, wizard_transform, wizard_size, gravity)) = wizard_query.get_single()
+    else {
+        return;
+    };
+    let dt = physics_dt(&fixed_time);
+    // work in the wizard's floor coordinates: x along the walking axis, y along gravity
+    let axis = gravity.direction.right();
+    let to_floor = |v: Vec2| {
+        if axis.x != 0.0 {
+            v
+        } else {
+            Vec2::new(v.y, v.x)
+        }
+    };
+    let wizard = Aabb::from_center(
+        to_floor(wizard_transform.translation.truncate()),
+        to_floor(Vec2::new(wizard_size.width, wizard_size.height)),
+    );
+    let wizard_speed = to_floor(Vec2::new(wizard_velocity.x, wizard_velocity.y)).x;
+
+    for (mut velocity, transform, size, mass) in object_query.iter_mut() {
+        let object = Aabb::from_center(
+            to_floor(transform.translation.truncate()),
+            to_floor(Vec2::new(size.width, size.height)),
+        );
+
+        // only pushes from the side, standing on a crate doesn't move it
+        if wizard.min.y >= object.max.y || wizard.max.y <= object.min.y {
+            continue;
+        }
+        let from_left = wizard.max.x <= object.min.x
+            && wizard_speed > 0.0
+            && wizard.max.x + wizard_speed * dt > object.min.x;
+        let from_right = wizard.min.x >= object.max.x
+            && wizard_speed < 0.0
+            && wizard.min.x + wizard_speed * dt < object.max.x;
+        let speed = velocity_along(&mut velocity, axis);
+        // a crate already sliding away faster isn't slowed down
+        if (from_left && wizard_speed > *speed) || (from_right && wizard_speed < *speed) {
+            // crates lighter than the wizard take all of its speed, not more
+            let share = mass.map_or(1.0, |mass| (1.0 / mass.0).min(1.0));
+            *speed += (wizard_speed - *speed) * share;
+        }
     }
 }
 
 // conditionals
-pub fn in_air(wizard_velocity_query: Query<&Velocity, With<Wizard>>) -> bool {
-    let wizard_velocity = wizard_velocity_query.single();
-    wizard_velocity.y != 0.0
+// there is no wizard between levels, which counts as not standing on anything
+pub fn in_air(wizard_contacts_query: Query<&Contacts, With<Wizard>>) -> bool {
+    let Ok(contacts) = wizard_contacts_query.get_single() else {
+        return true;
+    };
+    !contacts.floor
+}
+pub fn wizard_spawned(wizard_query: Query<(), With<Wizard>>) -> bool {
+    !wizard_query.is_empty()
 }
-pub fn out_of_screen(
-    wizard_query: Query<(&Transform, &EntitySize), With<Wizard>>,
-    window_query: Query<&Window, With<PrimaryWindow>>,
-) -> bool {
-    let (wizard_transform, player_size) = wizard_query.single();
-    let window = window_query.single();
-
-    let highx = wizard_transform.translation.x + player_size.width / 2.0;
-    let lowx = wizard_transform.translation.x - player_size.width / 2.0;
-    let highy = wizard_transform.translation.y + player_size.height / 2.0;
-    let lowy = wizard_transform.translation.y - player_size.height / 2.0;
-
-    lowx > window.width() || highx < 0.0 || lowy > window.height() || highy < 0.0
+// sent when the wizard leaves the world, touches a death zone or an enemy
+pub struct WizardDied;
+
+#[allow(clippy::type_complexity)]
+pub fn check_wizard_death(
+    wizard_query: Query<(&EntitySize, &PhysicsInterpolation), With<Wizard>>,
+    bounds: Res<WorldBounds>,
+    death_zone_query: Query<&DeathZone>,
+    enemy_query: Query<(&PhysicsInterpolation, &EntitySize), (With<Enemy>, Without<Wizard>)>,
+    mut died: EventWriter<WizardDied>,
+) {
+    let Ok((player_size, interpolation)) = wizard_query.get_single() else {
+        return;
+    };
+
+    let wizard = interpolation.aabb(player_size);
+    let in_death_zone = death_zone_query.iter().any(|zone| zone.overlaps(&wizard));
+    // enemies are solid, so touching is as close as they get
+    let touching = wizard.expanded(CONTACT_DISTANCE);
+    let hit_enemy = enemy_query
+        .iter()
+        .any(|(interpolation, size)| touching.overlaps(&interpolation.aabb(size)));
+    let out_of_world = bounds.is_outside(&wizard);
+    if out_of_world || in_death_zone || hit_enemy {
+        died.send(WizardDied);
+    }
 }
 
 pub fn spawn_wizard(
     mut commands: Commands,
-    window_query: Query<&Window, With<PrimaryWindow>>,
+    spawn_point: Res<SpawnPoint>,
     mut gravity: ResMut<Gravity>,
+    config: Res<PhysicsConfig>,
 ) {
-    let window = window_query.single();
-    gravity.0 = GravityDirection::Down;
+    let spawn = spawn_point.spawn;
+    gravity.0 = spawn_point.gravity;
     commands.spawn((
         SpriteBundle {
             sprite: Sprite {
                 color: WIZARD_COLOR_PASSIVE,
-                custom_size: Some(WIZARD_SHAPE),
+                custom_size: Some(Vec2::splat(config.wizard_size)),
                 ..default()
             },
-            transform: Transform::from_xyz(window.width() / 6.0, window.height() / 5.0, 0.0),
+            transform: Transform::from_xyz(spawn.x, spawn.y, 0.0),
             ..default()
         },
         Wizard,
-        Velocity { x: 0.0, y: 0.0 },
+        LevelEntity,
+        PhysicsInterpolation::new(Vec2::new(spawn.x, spawn.y)),
+        Velocity {
+            x: spawn.velocity_x,
+            y: spawn.velocity_y,
+        },
         EntitySize {
-            width: WIZARD_SIZE,
-            height: WIZARD_SIZE,
+            width: config.wizard_size,
+            height: config.wizard_size,
         },
+        Contacts::default(),
+        BodyGravity::default(),
+        Inventory::default(),
     ));
 }
 
+// keeps the wizard the size from the physics config when it gets reloaded. it grows
+// away from its floor, and keeps its old size when the new one doesn't fit where it is
+#[allow(clippy::type_complexity)]
+pub fn resize_wizard(
+    mut wizard_query: Query<
+        (
+            &mut Sprite,
+            &mut EntitySize,
+            &mut Transform,
+            &mut PhysicsInterpolation,
+            &BodyGravity,
+        ),
+        With<Wizard>,
+    >,
+    platform_query: Query<&Platform>,
+    body_query: Query<(&PhysicsInterpolation, &EntitySize), Without<Wizard>>,
+    config: Res<PhysicsConfig>,
+) {
+    for (mut sprite, mut size, mut transform, mut interpolation, gravity) in wizard_query.iter_mut()
+    {
+        let old_size = Vec2::new(size.width, size.height);
+        let new_size = Vec2::splat(config.wizard_size);
+        if new_size == old_size {
+            continue;
+        }
+        let up = -gravity.direction.down();
+        let shift = up * (new_size - old_size).dot(up.abs()) / 2.0;
+        let resized = Aabb::from_center(interpolation.current + shift, new_size);
+        let blocked = platform_query
+            .iter()
+            .any(|platform| resized.overlaps(&Aabb::from(platform)))
+            || body_query
+                .iter()
+                .any(|(other, other_size)| resized.overlaps(&other.aabb(other_size)));
+        if blocked {
+            eprintln!(
+                "no room for a wizard of size {} here, keeping size {}",
+                config.wizard_size, size.width
+            );
+            continue;
+        }
+        sprite.custom_size = Some(new_size);
+        size.width = new_size.x;
+        size.height = new_size.y;
+        interpolation.previous += shift;
+        interpolation.current += shift;
+        transform.translation.x += shift.x;
+        transform.translation.y += shift.y;
+    }
+}
+
 pub fn set_active_color(mut wizard_sprite_query: Query<&mut Sprite, With<Wizard>>) {
-    let mut wizard_sprite = wizard_sprite_query.single_mut();
-    wizard_sprite.color = WIZARD_COLOR_ACTIVE;
+    for mut wizard_sprite in wizard_sprite_query.iter_mut() {
+        wizard_sprite.color = WIZARD_COLOR_ACTIVE;
+    }
 }
 pub fn set_passive_color(mut wizard_sprite_query: Query<&mut Sprite, With<Wizard>>) {
-    let mut wizard_sprite = wizard_sprite_query.single_mut();
-    wizard_sprite.color = WIZARD_COLOR_PASSIVE;
+    for mut wizard_sprite in wizard_sprite_query.iter_mut() {
+        wizard_sprite.color = WIZARD_COLOR_PASSIVE;
+    }
 }
 
 pub fn despawn_wizard(mut commands: Commands, wizard_query: Query<Entity, With<Wizard>>) {
-    let wizard = wizard_query.single();
-    commands.entity(wizard).despawn();
+    for wizard in wizard_query.iter() {
+        commands.entity(wizard).despawn();
+    }
 }